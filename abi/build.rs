@@ -13,10 +13,47 @@ fn main() {
         .field_attribute("reservation.Reservation.id", "#[builder(setter(skip))]")
         .with_builder_attribute(
             "reservation.Reservation",
-            &["uid", "resource_id", "note", "start", "end", "rstatus"],
+            &[
+                "uid",
+                "resource_id",
+                "note",
+                "start",
+                "end",
+                "rstatus",
+                "cancelled_at",
+                "cancel_reason",
+                "series_id",
+                "quantity",
+                "expires_at",
+            ],
             "#[builder(default)]",
         )
-        .compile(&["protos/reservation.proto"], &["protos"])
+        .type_attribute(
+            "reservation.QueryRequest",
+            "#[derive(derive_builder::Builder)]",
+        )
+        .with_builder_attribute(
+            "reservation.QueryRequest",
+            &["uid", "resource_id", "status", "desc"],
+            "#[builder(setter(into), default)]",
+        )
+        .with_builder_attribute(
+            "reservation.QueryRequest",
+            &["start", "end"],
+            "#[builder(setter(into, strip_option), default)]",
+        )
+        // served by the reflection service, see `rsys_abi::FILE_DESCRIPTOR_SET`
+        .file_descriptor_set_path("src/pb/reservation_descriptor.bin")
+        .compile(
+            &[
+                "protos/reservation.proto",
+                "protos/google/rpc/status.proto",
+                "protos/google/rpc/error_details.proto",
+                "protos/grpc/health/v1/health.proto",
+                "protos/grpc/reflection/v1alpha/reflection.proto",
+            ],
+            &["protos"],
+        )
         .unwrap();
 
     Command::new("cargo").args(["fmt"]).output().unwrap();
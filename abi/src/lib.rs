@@ -3,10 +3,14 @@ pub mod utils;
 
 use chrono::{DateTime, Utc};
 pub use pb::*;
+use std::fmt;
 pub use utils::*;
 
 extern crate derive_builder;
 
+/// encoded `FileDescriptorSet` of every compiled proto and its imports
+pub const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!("pb/reservation_descriptor.bin");
+
 impl Reservation {
     pub fn new_pending(
         uid: impl Into<String>,
@@ -43,10 +47,68 @@ impl<T: Into<String>> From<T> for ConfirmRequest {
 
 impl From<String> for CancelRequest {
     fn from(value: String) -> Self {
-        CancelRequest { id: value }
+        CancelRequest {
+            id: value,
+            ..Default::default()
+        }
+    }
+}
+
+impl UpdateRequest {
+    /// update the fields listed in `paths` of the reservation `rsvp.id`
+    pub fn masked(rsvp: Reservation, paths: &[&str]) -> Self {
+        UpdateRequest {
+            id: rsvp.id.clone(),
+            reservation: Some(rsvp),
+            update_mask: Some(prost_types::FieldMask {
+                paths: paths.iter().map(|p| p.to_string()).collect(),
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+impl UpdateResourceRequest {
+    /// update the fields listed in `paths` of the resource `resource.id`
+    pub fn masked(resource: Resource, paths: &[&str]) -> Self {
+        UpdateResourceRequest {
+            resource: Some(resource),
+            update_mask: Some(prost_types::FieldMask {
+                paths: paths.iter().map(|p| p.to_string()).collect(),
+            }),
+        }
+    }
+}
+
+impl ReservationConflictInfo {
+    /// type url used when packing into `google.rpc.Status.details`
+    pub const TYPE_URL: &'static str = "type.googleapis.com/reservation.ReservationConflictInfo";
+
+    pub fn from_ids(ids: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        ReservationConflictInfo {
+            conflicts: ids
+                .into_iter()
+                .map(|id| ReservationConflict {
+                    id: id.into(),
+                    ..Default::default()
+                })
+                .collect(),
+        }
     }
 }
 
+impl fmt::Display for ReservationConflictInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ids: Vec<&str> = self.conflicts.iter().map(|c| c.id.as_str()).collect();
+        write!(f, "[{}]", ids.join(", "))
+    }
+}
+
+impl google::rpc::ErrorInfo {
+    /// type url used when packing into `google.rpc.Status.details`
+    pub const TYPE_URL: &'static str = "type.googleapis.com/google.rpc.ErrorInfo";
+}
+
 #[cfg(test)]
 mod tests {
     use crate::*;
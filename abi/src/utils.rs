@@ -1,4 +1,4 @@
-use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDateTime, Utc};
 use prost_types::Timestamp;
 
 pub fn convert_to_datetime(ts: Timestamp) -> DateTime<FixedOffset> {
@@ -15,6 +15,14 @@ pub fn convert_to_timestamp(dt: DateTime<Utc>) -> Timestamp {
     }
 }
 
+/// `None` when `d` is longer than a chrono `Duration` can hold
+pub fn convert_to_duration(d: prost_types::Duration) -> Option<Duration> {
+    if d.seconds.unsigned_abs() > (i64::MAX / 1000) as u64 {
+        return None;
+    }
+    Duration::seconds(d.seconds).checked_add(&Duration::nanoseconds(d.nanos as _))
+}
+
 pub struct DateTimeOffset(pub DateTime<FixedOffset>);
 
 impl From<DateTimeOffset> for Timestamp {
@@ -30,7 +38,7 @@ impl From<DateTimeOffset> for Timestamp {
 pub fn parse_datetime(s: &str) -> Result<DateTime<Utc>, ()> {
     Ok(DateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%#z")
         .map_err(|_| {
-            println!(" parse_datetime:{:?} ERROR", s);
+            tracing::debug!(input = s, "parse_datetime: not a datetime");
         })?
         .with_timezone(&Utc))
 }
@@ -44,8 +52,21 @@ macro_rules! printlnn {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn macro_test() {
         printlnn!("1", "2", "3", "4", "5", "6", "7");
     }
+
+    #[test]
+    fn duration_out_of_range() {
+        let d = |seconds, nanos| prost_types::Duration { seconds, nanos };
+        assert_eq!(
+            convert_to_duration(d(90, 500)),
+            Some(Duration::nanoseconds(90_000_000_500))
+        );
+        assert_eq!(convert_to_duration(d(i64::MAX, 0)), None);
+        assert_eq!(convert_to_duration(d(i64::MIN, 0)), None);
+    }
 }
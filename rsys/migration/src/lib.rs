@@ -2,6 +2,16 @@ pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
 mod m20230814_033626_1;
+mod m20261018_100000_reservation_timespan;
+mod m20261018_110000_reservation_change_status;
+mod m20261018_120000_reservation_cancel;
+mod m20261018_130000_reservation_series;
+mod m20261018_140000_create_resources;
+mod m20261018_150000_reservation_quantity;
+mod m20261018_160000_reservation_hold;
+mod m20261018_170000_create_waitlist;
+mod m20261018_180000_reservation_idempotency_key;
+mod m20261018_190000_reservation_overlap_guard;
 
 pub struct Migrator;
 
@@ -11,6 +21,16 @@ impl MigratorTrait for Migrator {
         vec![
             Box::new(m20220101_000001_create_table::Migration),
             Box::new(m20230814_033626_1::Migration),
+            Box::new(m20261018_100000_reservation_timespan::Migration),
+            Box::new(m20261018_110000_reservation_change_status::Migration),
+            Box::new(m20261018_120000_reservation_cancel::Migration),
+            Box::new(m20261018_130000_reservation_series::Migration),
+            Box::new(m20261018_140000_create_resources::Migration),
+            Box::new(m20261018_150000_reservation_quantity::Migration),
+            Box::new(m20261018_160000_reservation_hold::Migration),
+            Box::new(m20261018_170000_create_waitlist::Migration),
+            Box::new(m20261018_180000_reservation_idempotency_key::Migration),
+            Box::new(m20261018_190000_reservation_overlap_guard::Migration),
         ]
     }
 }
@@ -0,0 +1,52 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// keep in sync with `rsys::error`, the name is used to recognise conflicts
+const CONFLICT_CONSTRAINT: &str = "reservations_conflict";
+
+/// `RESERVATION_STATUS_CANCELLED`, cancelled rows never block a resource
+const STATUS_CANCELLED: i32 = 4;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        db.execute_unprepared("CREATE EXTENSION IF NOT EXISTS btree_gist")
+            .await?;
+
+        // derived from start_time/end_time so the two can never disagree
+        db.execute_unprepared(
+            "ALTER TABLE reservations ADD COLUMN IF NOT EXISTS timespan tstzrange \
+             GENERATED ALWAYS AS (tstzrange(start_time, end_time)) STORED",
+        )
+        .await?;
+
+        // `id WITH <>` never excludes anything by itself, it is there so the
+        // violation detail reports the id of the reservation we collided with
+        db.execute_unprepared(&format!(
+            "ALTER TABLE reservations ADD CONSTRAINT {CONFLICT_CONSTRAINT} \
+             EXCLUDE USING gist (resource_id WITH =, timespan WITH &&, id WITH <>) \
+             WHERE (r_status IS DISTINCT FROM {STATUS_CANCELLED})"
+        ))
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        db.execute_unprepared(&format!(
+            "ALTER TABLE reservations DROP CONSTRAINT IF EXISTS {CONFLICT_CONSTRAINT}"
+        ))
+        .await?;
+
+        db.execute_unprepared("ALTER TABLE reservations DROP COLUMN IF EXISTS timespan")
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(ReservationChanges::Table)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(ReservationChanges::OldStatus).integer(),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(ReservationChanges::NewStatus).integer(),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(ReservationChanges::ChangedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(ReservationChanges::Table)
+                    .drop_column(ReservationChanges::OldStatus)
+                    .drop_column(ReservationChanges::NewStatus)
+                    .drop_column(ReservationChanges::ChangedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ReservationChanges {
+    Table,
+    OldStatus,
+    NewStatus,
+    ChangedAt,
+}
@@ -0,0 +1,40 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(Reservations::Table)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(Reservations::CancelledAt).timestamp_with_time_zone(),
+                    )
+                    .add_column_if_not_exists(ColumnDef::new(Reservations::CancelReason).text())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(Reservations::Table)
+                    .drop_column(Reservations::CancelledAt)
+                    .drop_column(Reservations::CancelReason)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Reservations {
+    Table,
+    CancelledAt,
+    CancelReason,
+}
@@ -0,0 +1,55 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(Reservations::Table)
+                    .add_column_if_not_exists(ColumnDef::new(Reservations::SeriesId).uuid())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("reservations_series_id_idx")
+                    .table(Reservations::Table)
+                    .col(Reservations::SeriesId)
+                    .col(Reservations::StartTime)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("reservations_series_id_idx")
+                    .table(Reservations::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(Reservations::Table)
+                    .drop_column(Reservations::SeriesId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Reservations {
+    Table,
+    SeriesId,
+    StartTime,
+}
@@ -0,0 +1,75 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Resources::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Resources::Id)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Resources::Name).string().not_null())
+                    .col(ColumnDef::new(Resources::ResourceType).string().not_null())
+                    .col(
+                        ColumnDef::new(Resources::Capacity)
+                            .integer()
+                            .not_null()
+                            .default(1),
+                    )
+                    .col(
+                        ColumnDef::new(Resources::Timezone)
+                            .string()
+                            .not_null()
+                            .default("UTC"),
+                    )
+                    .col(
+                        ColumnDef::new(Resources::Active)
+                            .boolean()
+                            .not_null()
+                            .default(true),
+                    )
+                    .col(
+                        ColumnDef::new(Resources::Metadata)
+                            .json_binary()
+                            .not_null()
+                            .default(Expr::cust("'{}'::jsonb")),
+                    )
+                    .col(
+                        ColumnDef::new(Resources::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Resources::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Resources {
+    Table,
+    Id,
+    Name,
+    ResourceType,
+    Capacity,
+    Timezone,
+    Active,
+    Metadata,
+    CreatedAt,
+}
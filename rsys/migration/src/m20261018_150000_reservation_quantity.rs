@@ -0,0 +1,87 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// the exclusion constraint added by `m20261018_100000_reservation_timespan`
+const CONFLICT_CONSTRAINT: &str = "reservations_conflict";
+
+/// `RESERVATION_STATUS_CANCELLED`
+const STATUS_CANCELLED: i32 = 4;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(Reservations::Table)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(Reservations::Quantity)
+                            .integer()
+                            .not_null()
+                            .default(1),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // resources may now hold several reservations at once, overlaps are
+        // checked against the capacity under a lock on the resource row
+        let db = manager.get_connection();
+        db.execute_unprepared(&format!(
+            "ALTER TABLE reservations DROP CONSTRAINT IF EXISTS {CONFLICT_CONSTRAINT}"
+        ))
+        .await?;
+
+        // the constraint's index served the overlap lookups so far
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("reservations_resource_start_idx")
+                    .table(Reservations::Table)
+                    .col(Reservations::ResourceId)
+                    .col(Reservations::StartTime)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("reservations_resource_start_idx")
+                    .table(Reservations::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        // fails while overlapping live reservations exist
+        let db = manager.get_connection();
+        db.execute_unprepared(&format!(
+            "ALTER TABLE reservations ADD CONSTRAINT {CONFLICT_CONSTRAINT} \
+             EXCLUDE USING gist (resource_id WITH =, timespan WITH &&, id WITH <>) \
+             WHERE (r_status IS DISTINCT FROM {STATUS_CANCELLED})"
+        ))
+        .await?;
+
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(Reservations::Table)
+                    .drop_column(Reservations::Quantity)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Reservations {
+    Table,
+    Quantity,
+    ResourceId,
+    StartTime,
+}
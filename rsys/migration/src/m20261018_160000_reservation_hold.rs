@@ -0,0 +1,54 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// `RESERVATION_STATUS_PENDING`, only pending reservations can be holds
+const STATUS_PENDING: i32 = 1;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(Reservations::Table)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(Reservations::ExpiresAt).timestamp_with_time_zone(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // the sweeper only ever looks at pending holds
+        manager
+            .get_connection()
+            .execute_unprepared(&format!(
+                "CREATE INDEX IF NOT EXISTS reservations_hold_expiry_idx \
+                 ON reservations (expires_at) WHERE r_status = {STATUS_PENDING}"
+            ))
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared("DROP INDEX IF EXISTS reservations_hold_expiry_idx")
+            .await?;
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(Reservations::Table)
+                    .drop_column(Reservations::ExpiresAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Reservations {
+    Table,
+    ExpiresAt,
+}
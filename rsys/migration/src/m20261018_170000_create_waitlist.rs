@@ -0,0 +1,88 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Waitlist::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Waitlist::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key()
+                            .default(PgFunc::gen_random_uuid()),
+                    )
+                    .col(ColumnDef::new(Waitlist::UserId).string().not_null())
+                    .col(ColumnDef::new(Waitlist::ResourceId).string().not_null())
+                    .col(
+                        ColumnDef::new(Waitlist::StartTime)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Waitlist::EndTime)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Waitlist::Quantity)
+                            .integer()
+                            .not_null()
+                            .default(1),
+                    )
+                    .col(ColumnDef::new(Waitlist::Note).text())
+                    .col(
+                        ColumnDef::new(Waitlist::Priority)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(Waitlist::ReservationId).uuid())
+                    .col(
+                        ColumnDef::new(Waitlist::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // promotion looks for entries still waiting on one resource
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "CREATE INDEX IF NOT EXISTS waitlist_waiting_idx \
+                 ON waitlist (resource_id, start_time) WHERE reservation_id IS NULL",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Waitlist::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Waitlist {
+    Table,
+    Id,
+    UserId,
+    ResourceId,
+    StartTime,
+    EndTime,
+    Quantity,
+    Note,
+    Priority,
+    ReservationId,
+    CreatedAt,
+}
@@ -0,0 +1,49 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(Reservations::Table)
+                    .add_column_if_not_exists(ColumnDef::new(Reservations::IdempotencyKey).text())
+                    .to_owned(),
+            )
+            .await?;
+
+        // keys are chosen by clients, so they only have to be unique per user
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "CREATE UNIQUE INDEX IF NOT EXISTS reservations_idempotency_key_idx \
+                 ON reservations (user_id, idempotency_key) WHERE idempotency_key IS NOT NULL",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared("DROP INDEX IF EXISTS reservations_idempotency_key_idx")
+            .await?;
+        manager
+            .alter_table(
+                sea_query::Table::alter()
+                    .table(Reservations::Table)
+                    .drop_column(Reservations::IdempotencyKey)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Reservations {
+    Table,
+    IdempotencyKey,
+}
@@ -0,0 +1,88 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// reported as the violated constraint, like the exclusion constraint this
+/// guard stands in for. `rsys::error` recognises conflicts by it and reads the
+/// existing id from the end of the message.
+const CONFLICT_CONSTRAINT: &str = "reservations_conflict";
+
+/// `RESERVATION_STATUS_CANCELLED`, cancelled rows never block a resource
+const STATUS_CANCELLED: i32 = 4;
+
+/// `reservations_conflict` was dropped once resources could hold several
+/// reservations at a time. for resources of capacity 1 overlaps are still
+/// refused by the database, whoever writes the row.
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        db.execute_unprepared(
+            "CREATE INDEX IF NOT EXISTS reservations_timespan_idx \
+             ON reservations USING gist (resource_id, timespan)",
+        )
+        .await?;
+
+        // the resource row lock serialises bookings of one resource, the same
+        // lock `rsys` takes before its capacity check. expired holds no
+        // longer block, whether or not the sweeper got to them.
+        db.execute_unprepared(&format!(
+            r#"CREATE OR REPLACE FUNCTION reservations_overlap_guard() RETURNS trigger
+            LANGUAGE plpgsql SET search_path FROM CURRENT AS $$
+            DECLARE
+                existing uuid;
+            BEGIN
+                IF NEW.r_status IS NOT DISTINCT FROM {STATUS_CANCELLED}
+                    OR NEW.expires_at <= now() THEN
+                    RETURN NULL;
+                END IF;
+                PERFORM 1 FROM resources
+                    WHERE id = NEW.resource_id AND capacity = 1 FOR UPDATE;
+                IF NOT FOUND THEN
+                    RETURN NULL;
+                END IF;
+                SELECT id INTO existing FROM reservations
+                    WHERE resource_id = NEW.resource_id
+                        AND timespan && NEW.timespan
+                        AND id <> NEW.id
+                        AND r_status IS DISTINCT FROM {STATUS_CANCELLED}
+                        AND (expires_at IS NULL OR expires_at > now())
+                    LIMIT 1;
+                IF FOUND THEN
+                    RAISE EXCEPTION 'reservation % overlaps %', NEW.id, existing
+                        USING ERRCODE = 'exclusion_violation',
+                              CONSTRAINT = '{CONFLICT_CONSTRAINT}',
+                              TABLE = 'reservations';
+                END IF;
+                RETURN NULL;
+            END
+            $$"#
+        ))
+        .await?;
+
+        // after the row is written, a BEFORE trigger would not see `timespan` yet
+        db.execute_unprepared(
+            "CREATE TRIGGER reservations_overlap_guard \
+             AFTER INSERT OR UPDATE OF resource_id, start_time, end_time, r_status, expires_at \
+             ON reservations FOR EACH ROW EXECUTE FUNCTION reservations_overlap_guard()",
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        db.execute_unprepared("DROP TRIGGER IF EXISTS reservations_overlap_guard ON reservations")
+            .await?;
+        db.execute_unprepared("DROP FUNCTION IF EXISTS reservations_overlap_guard()")
+            .await?;
+        db.execute_unprepared("DROP INDEX IF EXISTS reservations_timespan_idx")
+            .await?;
+
+        Ok(())
+    }
+}
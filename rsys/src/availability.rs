@@ -0,0 +1,183 @@
+use chrono::{DateTime, Duration, FixedOffset};
+
+use crate::{entities::reservations, error::RsysError};
+
+/// longest window one availability search may cover
+pub const MAX_WINDOW_DAYS: i64 = 92;
+/// upper bound on the candidate start times returned per resource
+pub const MAX_SLOTS: usize = 2000;
+
+pub type Interval = (DateTime<FixedOffset>, DateTime<FixedOffset>);
+/// an interval and the capacity taken during it
+pub type Load = (DateTime<FixedOffset>, DateTime<FixedOffset>, i32);
+
+pub fn load(r: &reservations::Model) -> Option<Load> {
+    Some((r.start_time?, r.end_time?, r.quantity))
+}
+
+/// the parts of `window` where the summed quantity of `loads` is above
+/// `limit`, sorted and merged. bookings only touching at an end do not overlap.
+pub fn over_limit(window: Interval, loads: &[Load], limit: i32) -> Vec<Interval> {
+    let (start, end) = window;
+    let mut events: Vec<_> = loads
+        .iter()
+        .map(|(s, e, q)| ((*s).max(start), (*e).min(end), *q))
+        .filter(|(s, e, _)| s < e)
+        .flat_map(|(s, e, q)| [(s, q), (e, -q)])
+        .collect();
+    events.sort();
+
+    let mut over = vec![];
+    let mut level = 0;
+    let mut since = None;
+    let mut events = events.into_iter().peekable();
+    while let Some((at, delta)) = events.next() {
+        level += delta;
+        if events.peek().is_some_and(|(next, _)| *next == at) {
+            continue;
+        }
+        match (level > limit, since) {
+            (true, None) => since = Some(at),
+            (false, Some(from)) => {
+                over.push((from, at));
+                since = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(from) = since {
+        over.push((from, end));
+    }
+    over
+}
+
+/// gaps of at least `min` between the `busy` intervals inside `window`.
+/// `busy` must be sorted by start and may overlap or stick out of the window.
+pub fn free_intervals(window: Interval, busy: &[Interval], min: Duration) -> Vec<Interval> {
+    let (start, end) = window;
+    let mut free = vec![];
+    let mut cursor = start;
+    for (busy_start, busy_end) in busy {
+        if *busy_start >= end {
+            break;
+        }
+        if *busy_start > cursor {
+            free.push((cursor, *busy_start));
+        }
+        cursor = cursor.max(*busy_end);
+    }
+    if cursor < end {
+        free.push((cursor, end));
+    }
+    free.retain(|(s, e)| *e - *s >= min);
+    free
+}
+
+/// start times on a grid of `step` from `anchor` where `length` fits into
+/// one of the `free` intervals
+pub fn slots(
+    free: &[Interval],
+    anchor: DateTime<FixedOffset>,
+    length: Duration,
+    step: Duration,
+) -> Result<Vec<DateTime<FixedOffset>>, RsysError> {
+    let step_ms = step.num_milliseconds();
+    if step_ms <= 0 {
+        return Err(RsysError::InvalidSearch(
+            "granularity must be positive".to_string(),
+        ));
+    }
+    let mut found = vec![];
+    for (start, end) in free {
+        // first grid point at or after `start`
+        let offset = (*start - anchor).num_milliseconds();
+        let steps = (offset + step_ms - 1).div_euclid(step_ms);
+        let mut at = anchor + Duration::milliseconds(steps * step_ms);
+        while at + length <= *end {
+            if found.len() == MAX_SLOTS {
+                return Err(RsysError::InvalidSearch(format!(
+                    "more than {} slots, use a coarser granularity",
+                    MAX_SLOTS
+                )));
+            }
+            found.push(at);
+            at += step;
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32, minute: u32) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(&format!("2023-10-18T{:02}:{:02}:00+00:00", hour, minute))
+            .unwrap()
+    }
+
+    #[test]
+    fn gaps_between_bookings() {
+        let busy = vec![
+            (at(7, 0), at(9, 30)),
+            (at(10, 0), at(11, 0)),
+            (at(10, 30), at(12, 0)),
+            (at(15, 0), at(19, 0)),
+        ];
+        let free = free_intervals((at(8, 0), at(18, 0)), &busy, Duration::minutes(30));
+        assert_eq!(free, vec![(at(9, 30), at(10, 0)), (at(12, 0), at(15, 0))]);
+
+        let free = free_intervals((at(8, 0), at(18, 0)), &busy, Duration::hours(1));
+        assert_eq!(free, vec![(at(12, 0), at(15, 0))]);
+
+        let free = free_intervals((at(8, 0), at(18, 0)), &[], Duration::hours(1));
+        assert_eq!(free, vec![(at(8, 0), at(18, 0))]);
+    }
+
+    #[test]
+    fn usage_over_capacity() {
+        let loads = vec![
+            (at(8, 0), at(10, 0), 1),
+            (at(9, 0), at(11, 0), 2),
+            (at(10, 0), at(12, 0), 1),
+            (at(12, 0), at(13, 0), 3),
+        ];
+        let window = (at(8, 0), at(18, 0));
+        assert_eq!(
+            over_limit(window, &loads, 2),
+            vec![(at(9, 0), at(11, 0)), (at(12, 0), at(13, 0))]
+        );
+        assert_eq!(over_limit(window, &loads, 3), vec![]);
+        assert_eq!(over_limit(window, &loads, 0), vec![(at(8, 0), at(13, 0))]);
+        // capacity 1 is the old exclusive behaviour
+        assert_eq!(
+            over_limit((at(10, 30), at(11, 30)), &loads, 0),
+            vec![(at(10, 30), at(11, 30))]
+        );
+    }
+
+    #[test]
+    fn slots_on_grid() {
+        let free = vec![(at(9, 10), at(10, 0)), (at(12, 0), at(13, 30))];
+        let found = slots(
+            &free,
+            at(8, 0),
+            Duration::minutes(30),
+            Duration::minutes(15),
+        )
+        .unwrap();
+        assert_eq!(
+            found,
+            vec![
+                at(9, 15),
+                at(9, 30),
+                at(12, 0),
+                at(12, 15),
+                at(12, 30),
+                at(12, 45),
+                at(13, 0),
+            ]
+        );
+        assert!(slots(&free, at(8, 0), Duration::minutes(30), Duration::zero()).is_err());
+    }
+}
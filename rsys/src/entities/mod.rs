@@ -5,3 +5,5 @@ pub mod prelude;
 pub mod post;
 pub mod reservation_changes;
 pub mod reservations;
+pub mod resources;
+pub mod waitlist;
@@ -3,3 +3,5 @@
 pub use super::post::Entity as Post;
 pub use super::reservation_changes::Entity as ReservationChanges;
 pub use super::reservations::Entity as Reservations;
+pub use super::resources::Entity as Resources;
+pub use super::waitlist::Entity as Waitlist;
@@ -9,6 +9,9 @@ pub struct Model {
     pub id: i32,
     pub reservation_id: Option<Uuid>,
     pub op: Option<i32>,
+    pub old_status: Option<i32>,
+    pub new_status: Option<i32>,
+    pub changed_at: DateTimeWithTimeZone,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
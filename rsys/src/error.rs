@@ -1,26 +1,146 @@
+use rsys_abi::{ReservationConflictInfo, ReservationStatus};
+use sea_orm::{DbErr, RuntimeErr};
 use sqlx::postgres::PgDatabaseError;
 use thiserror::Error;
 
+/// what the overlap guard on `rsvp.reservations` reports itself as
+const CONFLICT_CONSTRAINT: &str = "reservations_conflict";
+/// postgres `exclusion_violation`
+const EXCLUSION_VIOLATION: &str = "23P01";
+
 #[derive(Error, Debug)]
 pub enum RsysError {
     #[error("unknown error")]
     Unknown,
-    #[error("db error")]
-    DbError(#[from] sea_orm::DbErr),
-    #[error("sqlx error")]
-    DbxError(sqlx::Error),
-    #[error("{0} error")]
+    #[error("db error: {0}")]
+    DbError(#[source] sea_orm::DbErr),
+    #[error("sqlx error: {0}")]
+    DbxError(#[source] sqlx::Error),
+    #[error("reservation error: {0}")]
     ReservationError(String),
-    #[error("already booked")]
-    AlreadyBooked,
+    #[error("already booked by {0}")]
+    AlreadyBooked(ReservationConflictInfo),
     #[error("no reservation")]
     NoReservation,
+    #[error("invalid cursor: {0}")]
+    InvalidCursor(String),
+    #[error("invalid field mask path: {0}")]
+    InvalidFieldMask(String),
+    #[error("reservation must start before it ends")]
+    InvalidTimespan,
+    #[error("invalid time: {0}")]
+    InvalidTime(String),
+    #[error("invalid rrule {0}")]
+    InvalidRRule(String),
+    #[error("reservation {0} is not part of a series")]
+    NotInSeries(String),
+    #[error("resource {0} is unknown or inactive")]
+    UnknownResource(String),
+    #[error("resource {0} still has reservations")]
+    ResourceInUse(String),
+    #[error("invalid resource: {0}")]
+    InvalidResource(String),
+    #[error("quantity {0} is more than the resource holds")]
+    InvalidQuantity(i32),
+    #[error("invalid hold: {0}")]
+    InvalidHold(String),
+    #[error("hold {0} has expired")]
+    HoldExpired(String),
+    #[error("invalid waitlist entry: {0}")]
+    InvalidWaitlistEntry(String),
+    #[error("waitlist entry {0} not found")]
+    UnknownWaitlistEntry(String),
+    #[error("invalid idempotency key: {0}")]
+    InvalidIdempotencyKey(String),
+    #[error("invalid batch: {0}")]
+    InvalidBatch(String),
+    #[error("invalid availability search: {0}")]
+    InvalidSearch(String),
+    #[error("unauthenticated: {0}")]
+    Unauthenticated(String),
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("invalid transition from {from:?} to {to:?}")]
+    InvalidTransition {
+        from: ReservationStatus,
+        to: ReservationStatus,
+    },
     #[error("config error: {0}")]
     ConfigError(String),
+    #[error("invalid config {key}: {reason}")]
+    InvalidConfig { key: String, reason: String },
     #[error("server error: {0}")]
     ServerError(String),
 }
 
+impl RsysError {
+    /// the variant's name, a stable label for metrics and logs
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RsysError::Unknown => "Unknown",
+            RsysError::DbError(_) => "DbError",
+            RsysError::DbxError(_) => "DbxError",
+            RsysError::ReservationError(_) => "ReservationError",
+            RsysError::AlreadyBooked(_) => "AlreadyBooked",
+            RsysError::NoReservation => "NoReservation",
+            RsysError::InvalidCursor(_) => "InvalidCursor",
+            RsysError::InvalidFieldMask(_) => "InvalidFieldMask",
+            RsysError::InvalidTimespan => "InvalidTimespan",
+            RsysError::InvalidTime(_) => "InvalidTime",
+            RsysError::InvalidRRule(_) => "InvalidRRule",
+            RsysError::NotInSeries(_) => "NotInSeries",
+            RsysError::UnknownResource(_) => "UnknownResource",
+            RsysError::ResourceInUse(_) => "ResourceInUse",
+            RsysError::InvalidResource(_) => "InvalidResource",
+            RsysError::InvalidQuantity(_) => "InvalidQuantity",
+            RsysError::InvalidHold(_) => "InvalidHold",
+            RsysError::HoldExpired(_) => "HoldExpired",
+            RsysError::InvalidWaitlistEntry(_) => "InvalidWaitlistEntry",
+            RsysError::UnknownWaitlistEntry(_) => "UnknownWaitlistEntry",
+            RsysError::InvalidIdempotencyKey(_) => "InvalidIdempotencyKey",
+            RsysError::InvalidBatch(_) => "InvalidBatch",
+            RsysError::InvalidSearch(_) => "InvalidSearch",
+            RsysError::Unauthenticated(_) => "Unauthenticated",
+            RsysError::PermissionDenied(_) => "PermissionDenied",
+            RsysError::InvalidTransition { .. } => "InvalidTransition",
+            RsysError::ConfigError(_) => "ConfigError",
+            RsysError::InvalidConfig { .. } => "InvalidConfig",
+            RsysError::ServerError(_) => "ServerError",
+        }
+    }
+
+    /// the database could not be reached or went away, as opposed to
+    /// rejecting what was asked of it. worth retrying later.
+    pub fn is_unavailable(&self) -> bool {
+        match self {
+            RsysError::DbError(DbErr::ConnectionAcquire(_)) => true,
+            RsysError::DbError(
+                DbErr::Conn(RuntimeErr::SqlxError(err))
+                | DbErr::Exec(RuntimeErr::SqlxError(err))
+                | DbErr::Query(RuntimeErr::SqlxError(err)),
+            ) => sqlx_unavailable(err),
+            RsysError::DbError(DbErr::Conn(_)) => true,
+            RsysError::DbxError(err) => sqlx_unavailable(err),
+            _ => false,
+        }
+    }
+}
+
+fn sqlx_unavailable(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Io(_)
+        | sqlx::Error::Tls(_)
+        | sqlx::Error::PoolTimedOut
+        | sqlx::Error::PoolClosed
+        | sqlx::Error::WorkerCrashed => true,
+        // connection exceptions and the server shutting down
+        sqlx::Error::Database(err) => err
+            .code()
+            .is_some_and(|code| code.starts_with("08") || code.starts_with("57P")),
+        _ => false,
+    }
+}
+
 impl PartialEq for RsysError {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -35,9 +155,12 @@ impl From<sqlx::Error> for RsysError {
         match err {
             sqlx::Error::Database(err) => {
                 let pgerr: &PgDatabaseError = err.downcast_ref();
+                if let Some(info) = conflict_info(pgerr) {
+                    return RsysError::AlreadyBooked(info);
+                }
                 match (pgerr.schema(), pgerr.table()) {
                     (Some("rsvp"), Some("reservations")) => {
-                        RsysError::ReservationError("reservationsX".to_owned())
+                        RsysError::ReservationError(pgerr.message().to_owned())
                     }
                     _ => RsysError::DbxError(sqlx::Error::Database(err)),
                 }
@@ -47,15 +170,39 @@ impl From<sqlx::Error> for RsysError {
     }
 }
 
-// impl From<sea_orm::DbErr> for RsysError {
-//     fn from(value: sea_orm::DbErr) -> Self {
-//         RsysError::DbError(value)
-//     }
-// }
+impl From<DbErr> for RsysError {
+    fn from(err: DbErr) -> Self {
+        if let DbErr::Exec(RuntimeErr::SqlxError(sqlx::Error::Database(dberr)))
+        | DbErr::Query(RuntimeErr::SqlxError(sqlx::Error::Database(dberr))) = &err
+        {
+            if let Some(info) = conflict_info(dberr.downcast_ref()) {
+                return RsysError::AlreadyBooked(info);
+            }
+        }
+        RsysError::DbError(err)
+    }
+}
+
+/// the existing reservation a write collided with, if `err` comes from the
+/// overlap guard. postgres only reports the id.
+fn conflict_info(err: &PgDatabaseError) -> Option<ReservationConflictInfo> {
+    if err.code() != EXCLUSION_VIOLATION || err.constraint() != Some(CONFLICT_CONSTRAINT) {
+        return None;
+    }
+    Some(ReservationConflictInfo::from_ids(parse_conflict_id(
+        err.message(),
+    )))
+}
+
+/// the guard raises `reservation <new id> overlaps <existing id>`
+fn parse_conflict_id(message: &str) -> Option<String> {
+    let (_, id) = message.rsplit_once(" overlaps ")?;
+    Some(id.trim().to_string()).filter(|id| !id.is_empty())
+}
 
 #[cfg(test)]
 mod tests {
-    use crate::error::RsysError;
+    use crate::error::{parse_conflict_id, RsysError};
 
     #[test]
     fn debug_errors() {
@@ -66,4 +213,32 @@ mod tests {
         let err = RsysError::ConfigError("config".to_string());
         println!("{:?}\n{}", err, err);
     }
+
+    #[test]
+    fn db_errors_keep_their_source() {
+        let err = RsysError::from(sqlx::Error::PoolTimedOut);
+        assert!(err.is_unavailable());
+        assert!(err.to_string().contains("pool timed out"), "{}", err);
+        assert!(std::error::Error::source(&err).is_some());
+
+        let err = RsysError::from(sea_orm::DbErr::ConnectionAcquire(
+            sea_orm::error::ConnAcquireErr::Timeout,
+        ));
+        assert!(err.is_unavailable());
+
+        let err = RsysError::from(sea_orm::DbErr::Custom("boom".to_string()));
+        assert!(!err.is_unavailable());
+        assert_eq!(err.to_string(), "db error: Custom Error: boom");
+        assert!(!RsysError::NoReservation.is_unavailable());
+    }
+
+    #[test]
+    fn parse_conflict_message() {
+        let message = "reservation 6b1f0d5e-8d3c-4bde-9f0a-6b0e0c7d1a11 overlaps 1c9e3b4a-2f6d-4e8a-b7c5-0d9e8f7a6b5c";
+        assert_eq!(
+            parse_conflict_id(message),
+            Some("1c9e3b4a-2f6d-4e8a-b7c5-0d9e8f7a6b5c".to_string())
+        );
+        assert_eq!(parse_conflict_id("Key (id)=(1) already exists."), None);
+    }
 }
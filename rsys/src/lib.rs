@@ -1,43 +1,312 @@
+mod availability;
 pub mod entities;
 pub mod error;
 mod manager;
+mod pager;
+mod resource;
+mod rrule;
+pub mod status;
+mod waitlist;
 
 use async_trait::async_trait;
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDateTime, Utc};
 use error::*;
 use rand::Rng;
 use rand_distr::{Alphanumeric, Distribution};
 use rsys_abi::{
-    CancelRequest, ConfirmRequest, DateTimeOffset, GetRequest, ListenRequest, QueryRequest,
-    Reservation, UpdateRequest,
+    AvailabilityRequest, AvailabilityResponse, CancelRequest, CancelSeriesRequest, ConfirmRequest,
+    DateTimeOffset, FilterRequest, FilterResponse, GetRequest, ListenRequest, ListenResponse,
+    PurgeRequest, QueryRequest, Reservation, ReservationConflict, ReservationStatus,
+    ReserveBatchRequest, ReserveBatchResponse, ReserveRequest, ReserveSeriesRequest,
+    ReserveSeriesResponse, Resource, UpdateRequest, UpdateResourceRequest, UpdateSeriesRequest,
+    WaitlistEntry,
 };
 use sea_orm::DatabaseConnection;
 use sqlx::{postgres::PgRow, FromRow, Row};
+use std::fmt;
 use tokio::sync::mpsc::Receiver;
 
 #[async_trait]
 pub trait Rsvp {
+    /// book `rsvp` on behalf of its uid, which may not start it confirmed
     async fn create(&self, rsvp: Reservation) -> Result<Reservation, RsysError>;
 
-    async fn change_status(&self, change: ConfirmRequest) -> Result<Reservation, RsysError>;
+    /// create a pending hold that is cancelled unless confirmed within `ttl`
+    async fn hold(&self, rsvp: Reservation, ttl: Duration) -> Result<Reservation, RsysError>;
 
-    async fn update_note(&self, update: UpdateRequest) -> Result<Reservation, RsysError>;
+    /// `create` or `hold` as `reserve.hold_ttl` asks, replaying an
+    /// `idempotency_key` returns what the first request with it created
+    /// and `true`
+    async fn reserve(
+        &self,
+        reserve: ReserveRequest,
+        caller: &Caller,
+    ) -> Result<(Reservation, bool), RsysError>;
 
-    async fn delete(&self, cancel: CancelRequest) -> Result<usize, RsysError>;
+    /// cancel up to `limit` holds past their expiry, emitting
+    /// `OperateType::Expire` for each
+    async fn expire_holds(&self, limit: u64) -> Result<Vec<Reservation>, RsysError>;
 
-    async fn get(&self, cancel: GetRequest) -> Result<Reservation, RsysError>;
+    async fn change_status(
+        &self,
+        change: ConfirmRequest,
+        caller: &Caller,
+    ) -> Result<Reservation, RsysError>;
 
-    async fn query(&self, query: QueryRequest) -> Receiver<Result<Reservation, RsysError>>;
+    /// move a reservation to `to`, see `status::check_transition` for the rules
+    async fn transition(
+        &self,
+        id: &str,
+        to: ReservationStatus,
+        caller: &Caller,
+    ) -> Result<Reservation, RsysError>;
 
-    async fn listen(&self, listen: ListenRequest) -> Result<Vec<Reservation>, RsysError>;
+    /// apply the fields named by the update mask, a change of time or resource
+    /// is checked for conflicts like a new reservation
+    async fn update(
+        &self,
+        update: UpdateRequest,
+        caller: &Caller,
+    ) -> Result<Reservation, RsysError>;
+
+    /// book all of `batch.reservations` in one transaction, or none of them
+    /// and report why each failing one did not fit
+    async fn reserve_batch(
+        &self,
+        batch: ReserveBatchRequest,
+        caller: &Caller,
+    ) -> Result<ReserveBatchResponse, RsysError>;
+
+    /// book every occurrence of `series.rrule`, see `ConflictPolicy` for
+    /// what happens when some of them are taken
+    async fn reserve_series(
+        &self,
+        series: ReserveSeriesRequest,
+        caller: &Caller,
+    ) -> Result<ReserveSeriesResponse, RsysError>;
+
+    /// apply one update to the occurrences picked by `update.scope`
+    async fn update_series(
+        &self,
+        update: UpdateSeriesRequest,
+        caller: &Caller,
+    ) -> Result<Vec<Reservation>, RsysError>;
+
+    async fn cancel_series(
+        &self,
+        cancel: CancelSeriesRequest,
+        caller: &Caller,
+    ) -> Result<Vec<Reservation>, RsysError>;
+
+    /// soft delete, the reservation is kept as `RESERVATION_STATUS_CANCELLED`
+    async fn cancel(
+        &self,
+        cancel: CancelRequest,
+        caller: &Caller,
+    ) -> Result<Reservation, RsysError>;
+
+    /// hard delete reservations cancelled longer ago than the retention period
+    async fn purge(&self, purge: PurgeRequest, caller: &Caller) -> Result<u64, RsysError>;
+
+    async fn get(&self, get: GetRequest, caller: &Caller) -> Result<Reservation, RsysError>;
+
+    /// reservations matching `query`, only the caller's own unless admin
+    async fn query(
+        &self,
+        query: QueryRequest,
+        caller: &Caller,
+    ) -> Receiver<Result<Reservation, RsysError>>;
+
+    async fn filter(
+        &self,
+        filter: FilterRequest,
+        caller: &Caller,
+    ) -> Result<FilterResponse, RsysError>;
+
+    /// free intervals of each resource in the search window, computed against
+    /// reservations that are not cancelled
+    async fn availability(
+        &self,
+        search: AvailabilityRequest,
+    ) -> Result<AvailabilityResponse, RsysError>;
+
+    /// changes to reservations as they commit, only the caller's own unless admin
+    async fn listen(
+        &self,
+        listen: ListenRequest,
+        caller: &Caller,
+    ) -> Result<Receiver<Result<ListenResponse, RsysError>>, RsysError>;
+}
+
+/// the `rsvp.resources` registry reservations are checked against
+#[async_trait]
+pub trait ResourceRegistry {
+    async fn create_resource(&self, resource: Resource) -> Result<Resource, RsysError>;
+
+    async fn get_resource(&self, id: &str) -> Result<Resource, RsysError>;
+
+    async fn update_resource(&self, update: UpdateResourceRequest) -> Result<Resource, RsysError>;
+
+    async fn delete_resource(&self, id: &str) -> Result<Resource, RsysError>;
+
+    async fn list_resources(&self, include_inactive: bool) -> Result<Vec<Resource>, RsysError>;
+}
+
+/// interest in fully booked slots, promoted into pending reservations as
+/// cancellations and expired holds free up capacity
+#[async_trait]
+pub trait Waitlist {
+    /// queue `entry`, it is promoted at once when the slot has room for it.
+    /// `entry.priority` is only taken from admins, others queue at 0.
+    async fn join_waitlist(
+        &self,
+        entry: WaitlistEntry,
+        caller: &Caller,
+    ) -> Result<WaitlistEntry, RsysError>;
+
+    async fn leave_waitlist(&self, id: &str, caller: &Caller) -> Result<WaitlistEntry, RsysError>;
+}
+
+/// who a request is made on behalf of. users may only read and change their
+/// own reservations and waitlist entries, admins any of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Caller {
+    pub uid: String,
+    pub admin: bool,
+}
+
+impl Caller {
+    pub fn user(uid: impl Into<String>) -> Self {
+        Caller {
+            uid: uid.into(),
+            admin: false,
+        }
+    }
+
+    pub fn admin(uid: impl Into<String>) -> Self {
+        Caller {
+            uid: uid.into(),
+            admin: true,
+        }
+    }
+
+    /// whether something belonging to `owner` is open to this caller
+    pub fn may_access(&self, owner: Option<&str>) -> bool {
+        self.admin || (!self.uid.is_empty() && owner == Some(self.uid.as_str()))
+    }
+
+    pub fn check_access(&self, owner: Option<&str>) -> Result<(), RsysError> {
+        if self.may_access(owner) {
+            return Ok(());
+        }
+        Err(RsysError::PermissionDenied(format!(
+            "{} may not access reservations of {}",
+            self.uid,
+            owner.unwrap_or("nobody")
+        )))
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone)]
 pub struct ReservationManager {
     pub constr: String,
     db: DatabaseConnection,
 }
 
+impl fmt::Debug for ReservationManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReservationManager")
+            .field("constr", &redact_url(&self.constr))
+            .finish_non_exhaustive()
+    }
+}
+
+/// connections of the pool at one point in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStatus {
+    /// open connections, idle or in use
+    pub size: u32,
+    pub idle: usize,
+    pub max: u32,
+}
+
+/// how `ReservationManager::connect` sets up its pool
+#[derive(Clone, PartialEq, Eq)]
+pub struct DbOptions {
+    pub url: String,
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub connect_timeout: std::time::Duration,
+    pub acquire_timeout: std::time::Duration,
+    pub idle_timeout: std::time::Duration,
+    /// `statement_timeout` of every session, zero keeps the server's setting
+    pub statement_timeout: std::time::Duration,
+    /// attempts after the first failed connect, `retry_backoff` apart and
+    /// twice as long after every further failure
+    pub connect_retries: u32,
+    pub retry_backoff: std::time::Duration,
+}
+
+impl DbOptions {
+    /// sqlx's pool defaults, connecting once without a statement timeout
+    pub fn new(url: impl Into<String>) -> Self {
+        DbOptions {
+            url: url.into(),
+            max_connections: 10,
+            min_connections: 0,
+            connect_timeout: std::time::Duration::from_secs(30),
+            acquire_timeout: std::time::Duration::from_secs(30),
+            idle_timeout: std::time::Duration::from_secs(600),
+            statement_timeout: std::time::Duration::ZERO,
+            connect_retries: 0,
+            retry_backoff: std::time::Duration::from_millis(500),
+        }
+    }
+}
+
+impl fmt::Debug for DbOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DbOptions")
+            .field("url", &redact_url(&self.url))
+            .field("max_connections", &self.max_connections)
+            .field("min_connections", &self.min_connections)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("acquire_timeout", &self.acquire_timeout)
+            .field("idle_timeout", &self.idle_timeout)
+            .field("statement_timeout", &self.statement_timeout)
+            .field("connect_retries", &self.connect_retries)
+            .field("retry_backoff", &self.retry_backoff)
+            .finish()
+    }
+}
+
+/// `ts` as a UTC datetime, `InvalidTime` when a client sent one chrono can
+/// not represent
+pub(crate) fn try_convert_to_datetime(
+    ts: prost_types::Timestamp,
+) -> Result<DateTime<FixedOffset>, RsysError> {
+    u32::try_from(ts.nanos)
+        .ok()
+        .and_then(|nanos| NaiveDateTime::from_timestamp_opt(ts.seconds, nanos))
+        .map(|t| t.and_utc().fixed_offset())
+        .ok_or_else(|| {
+            RsysError::InvalidTime(format!("{}s {}ns is out of range", ts.seconds, ts.nanos))
+        })
+}
+
+/// `url` with its password masked, safe to log
+pub fn redact_url(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(mut parsed) => {
+            if parsed.password().is_some() {
+                let _ = parsed.set_password(Some("***"));
+            }
+            parsed.to_string()
+        }
+        Err(_) => "<invalid url>".to_string(),
+    }
+}
+
 impl From<entities::reservations::Model> for Reservation {
     fn from(val: entities::reservations::Model) -> Self {
         Reservation {
@@ -48,6 +317,58 @@ impl From<entities::reservations::Model> for Reservation {
             start: Some(DateTimeOffset(val.start_time.unwrap_or_default()).into()),
             end: Some(DateTimeOffset(val.end_time.unwrap_or_default()).into()),
             rstatus: val.r_status.unwrap_or_default(),
+            cancelled_at: val.cancelled_at.map(|t| DateTimeOffset(t).into()),
+            cancel_reason: val.cancel_reason.unwrap_or_default(),
+            series_id: val.series_id.map(|id| id.to_string()).unwrap_or_default(),
+            quantity: val.quantity,
+            expires_at: val.expires_at.map(|t| DateTimeOffset(t).into()),
+        }
+    }
+}
+
+impl From<entities::reservations::Model> for ReservationConflict {
+    fn from(val: entities::reservations::Model) -> Self {
+        ReservationConflict {
+            id: val.id.to_string(),
+            uid: val.user_id.unwrap_or_default(),
+            resource_id: val.resource_id.unwrap_or_default(),
+            start: val.start_time.map(|t| DateTimeOffset(t).into()),
+            end: val.end_time.map(|t| DateTimeOffset(t).into()),
+        }
+    }
+}
+
+impl From<entities::resources::Model> for Resource {
+    fn from(val: entities::resources::Model) -> Self {
+        Resource {
+            id: val.id,
+            name: val.name,
+            resource_type: val.resource_type,
+            capacity: val.capacity,
+            timezone: val.timezone,
+            active: val.active,
+            metadata: val.metadata.to_string(),
+            created_at: Some(DateTimeOffset(val.created_at).into()),
+        }
+    }
+}
+
+impl From<entities::waitlist::Model> for WaitlistEntry {
+    fn from(val: entities::waitlist::Model) -> Self {
+        WaitlistEntry {
+            id: val.id.to_string(),
+            uid: val.user_id,
+            resource_id: val.resource_id,
+            start: Some(DateTimeOffset(val.start_time).into()),
+            end: Some(DateTimeOffset(val.end_time).into()),
+            quantity: val.quantity,
+            note: val.note.unwrap_or_default(),
+            priority: val.priority,
+            reservation_id: val
+                .reservation_id
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+            created_at: Some(DateTimeOffset(val.created_at).into()),
         }
     }
 }
@@ -62,12 +383,21 @@ impl FromRow<'_, PgRow> for entities::reservations::Model {
             start_time: row.get(4),
             end_time: row.get(5),
             note: row.get(6),
+            // looked up by name, the generated `timespan` column sits in between
+            cancelled_at: row.get("cancelled_at"),
+            cancel_reason: row.get("cancel_reason"),
+            series_id: row.get("series_id"),
+            quantity: row.get("quantity"),
+            expires_at: row.get("expires_at"),
+            idempotency_key: row.get("idempotency_key"),
         })
     }
 }
 
+/// `DATABASE_URL` from the environment or `.env`, for tests and tooling. the
+/// server reads its connection string through `rsys_servi::config`.
 pub fn env_con_str() -> String {
-    dotenvy::var("DATABASE_URL").unwrap()
+    dotenvy::var("DATABASE_URL").expect("DATABASE_URL is not set, see .env")
 }
 
 pub fn generate_random_string(length: usize) -> String {
@@ -78,6 +408,17 @@ pub fn generate_random_string(length: usize) -> String {
         .collect()
 }
 
+pub fn generate_random_resource() -> Resource {
+    Resource {
+        id: generate_random_string(8),
+        name: generate_random_string(12),
+        resource_type: "room".to_string(),
+        capacity: 1,
+        active: true,
+        ..Default::default()
+    }
+}
+
 #[allow(dead_code)]
 pub fn generate_random_reservation() -> Reservation {
     Reservation::new_pending(
@@ -94,6 +435,11 @@ pub fn generate_random_reservation() -> Reservation {
 }
 
 #[cfg(test)]
+#[allow(
+    unused_imports,
+    clippy::explicit_counter_loop,
+    clippy::unnecessary_unwrap
+)]
 mod tests {
     use crate::entities::prelude::*;
     use crate::entities::reservations;
@@ -0,0 +1,97 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, FixedOffset};
+use sqlx::types::Uuid;
+
+use crate::error::RsysError;
+
+pub const DEFAULT_PAGE_SIZE: u64 = 10;
+pub const MAX_PAGE_SIZE: u64 = 100;
+
+/// clamp a requested page size into `1..=MAX_PAGE_SIZE`
+pub fn page_size(requested: i64) -> u64 {
+    if requested <= 0 {
+        DEFAULT_PAGE_SIZE
+    } else {
+        (requested as u64).min(MAX_PAGE_SIZE)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Next,
+    Prev,
+}
+
+/// keyset position in the (start_time, id) ordering used by `filter`,
+/// handed to clients base64 encoded so they treat it as opaque
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageCursor {
+    pub direction: Direction,
+    pub start: DateTime<FixedOffset>,
+    pub id: Uuid,
+}
+
+impl PageCursor {
+    pub fn encode(&self) -> String {
+        let direction = match self.direction {
+            Direction::Next => "n",
+            Direction::Prev => "p",
+        };
+        let raw = format!("{}|{}|{}", direction, self.start.to_rfc3339(), self.id);
+        URL_SAFE_NO_PAD.encode(raw)
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, RsysError> {
+        let invalid = || RsysError::InvalidCursor(cursor.to_string());
+        let raw = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+        let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+        let mut parts = raw.splitn(3, '|');
+        let direction = match parts.next() {
+            Some("n") => Direction::Next,
+            Some("p") => Direction::Prev,
+            _ => return Err(invalid()),
+        };
+        let start = parts
+            .next()
+            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+            .ok_or_else(invalid)?;
+        let id = parts
+            .next()
+            .and_then(|s| Uuid::parse_str(s).ok())
+            .ok_or_else(invalid)?;
+        Ok(PageCursor {
+            direction,
+            start,
+            id,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_roundtrip() {
+        let cursor = PageCursor {
+            direction: Direction::Prev,
+            start: DateTime::parse_from_rfc3339("2023-10-18T10:00:00.123456+08:00").unwrap(),
+            id: Uuid::parse_str("1c9e3b4a-2f6d-4e8a-b7c5-0d9e8f7a6b5c").unwrap(),
+        };
+        let encoded = cursor.encode();
+        println!("{}", encoded);
+        assert_eq!(PageCursor::decode(&encoded).unwrap(), cursor);
+        assert!(matches!(
+            PageCursor::decode("not a cursor"),
+            Err(RsysError::InvalidCursor(_))
+        ));
+    }
+
+    #[test]
+    fn page_size_capped() {
+        assert_eq!(page_size(0), DEFAULT_PAGE_SIZE);
+        assert_eq!(page_size(-3), DEFAULT_PAGE_SIZE);
+        assert_eq!(page_size(25), 25);
+        assert_eq!(page_size(10_000), MAX_PAGE_SIZE);
+    }
+}
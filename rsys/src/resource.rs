@@ -0,0 +1,341 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use chrono_tz::Tz;
+use rsys_abi::{Resource, UpdateResourceRequest};
+use sea_orm::{
+    prelude::Json, ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, EntityTrait,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set, TransactionTrait,
+};
+use std::str::FromStr;
+
+use crate::{
+    availability,
+    entities::prelude::{Reservations, Resources},
+    entities::{reservations, resources},
+    error::RsysError,
+    ReservationManager, ResourceRegistry,
+};
+
+/// the resource `id` if it exists and is active
+pub(crate) async fn active<C: ConnectionTrait>(
+    conn: &C,
+    id: &str,
+) -> Result<resources::Model, RsysError> {
+    find_active(conn, id, false).await
+}
+
+/// like `active`, but the row stays locked until the surrounding transaction
+/// ends. bookings of one resource are thereby checked against its capacity
+/// one at a time, and it cannot be deactivated meanwhile.
+pub(crate) async fn bookable<C: ConnectionTrait>(
+    conn: &C,
+    id: &str,
+) -> Result<resources::Model, RsysError> {
+    find_active(conn, id, true).await
+}
+
+async fn find_active<C: ConnectionTrait>(
+    conn: &C,
+    id: &str,
+    lock: bool,
+) -> Result<resources::Model, RsysError> {
+    let mut select = Resources::find_by_id(id.to_string());
+    if lock {
+        select = select.lock_exclusive();
+    }
+    select
+        .one(conn)
+        .await?
+        .filter(|r| r.active)
+        .ok_or_else(|| RsysError::UnknownResource(id.to_string()))
+}
+
+fn parse_metadata(metadata: &str) -> Result<Json, RsysError> {
+    if metadata.is_empty() {
+        return Ok(Json::Object(Default::default()));
+    }
+    match serde_json::from_str(metadata) {
+        Ok(Json::Object(map)) => Ok(Json::Object(map)),
+        _ => Err(RsysError::InvalidResource(
+            "metadata must be a JSON object".to_string(),
+        )),
+    }
+}
+
+fn check_capacity(capacity: i32) -> Result<i32, RsysError> {
+    if capacity < 1 {
+        return Err(RsysError::InvalidResource(
+            "capacity must be at least 1".to_string(),
+        ));
+    }
+    Ok(capacity)
+}
+
+/// the zone the calendar of `resource` is kept in
+pub(crate) fn timezone(resource: &resources::Model) -> Result<Tz, RsysError> {
+    resource
+        .timezone
+        .parse()
+        .map_err(|_| RsysError::InvalidResource(format!("unknown timezone {}", resource.timezone)))
+}
+
+/// an IANA zone name, "UTC" when empty
+fn timezone_or_utc(timezone: String) -> Result<String, RsysError> {
+    if timezone.is_empty() {
+        return Ok("UTC".to_string());
+    }
+    match Tz::from_str(&timezone) {
+        Ok(_) => Ok(timezone),
+        Err(_) => Err(RsysError::InvalidResource(format!(
+            "unknown timezone {}",
+            timezone
+        ))),
+    }
+}
+
+/// fail with `ResourceInUse` if the live reservations of `id` still to come
+/// need more than `capacity` at any instant. `id` must be locked.
+async fn ensure_fits<C: ConnectionTrait>(
+    conn: &C,
+    id: &str,
+    capacity: i32,
+) -> Result<(), RsysError> {
+    let now = Utc::now().fixed_offset();
+    let rows = Reservations::find()
+        .filter(
+            Condition::all()
+                .add(reservations::Column::ResourceId.eq(id))
+                .add(reservations::Column::EndTime.gt(now))
+                .add(ReservationManager::live()),
+        )
+        .all(conn)
+        .await?;
+    let loads: Vec<_> = rows.iter().filter_map(availability::load).collect();
+    let Some(end) = loads.iter().map(|(_, end, _)| *end).max() else {
+        return Ok(());
+    };
+    if availability::over_limit((now, end), &loads, capacity).is_empty() {
+        return Ok(());
+    }
+    Err(RsysError::ResourceInUse(id.to_string()))
+}
+
+#[async_trait]
+impl ResourceRegistry for ReservationManager {
+    async fn create_resource(&self, resource: Resource) -> Result<Resource, RsysError> {
+        if resource.id.is_empty() || resource.name.is_empty() {
+            return Err(RsysError::InvalidResource(
+                "id and name are required".to_string(),
+            ));
+        }
+        let r = resources::ActiveModel {
+            id: Set(resource.id),
+            name: Set(resource.name),
+            resource_type: Set(resource.resource_type),
+            capacity: Set(check_capacity(resource.capacity)?),
+            timezone: Set(timezone_or_utc(resource.timezone)?),
+            active: Set(resource.active),
+            metadata: Set(parse_metadata(&resource.metadata)?),
+            ..Default::default()
+        };
+        Ok(r.insert(&self.db).await?.into())
+    }
+
+    async fn get_resource(&self, id: &str) -> Result<Resource, RsysError> {
+        Resources::find_by_id(id.to_string())
+            .one(&self.db)
+            .await?
+            .map(Into::into)
+            .ok_or_else(|| RsysError::UnknownResource(id.to_string()))
+    }
+
+    async fn update_resource(&self, update: UpdateResourceRequest) -> Result<Resource, RsysError> {
+        let patch = update.resource.unwrap_or_default();
+        let paths = update.update_mask.map(|m| m.paths).unwrap_or_default();
+
+        let txn = self.db.begin().await?;
+        let e = Resources::find_by_id(patch.id.clone())
+            .lock_exclusive()
+            .one(&txn)
+            .await?
+            .ok_or_else(|| RsysError::UnknownResource(patch.id.clone()))?;
+        let mut e: resources::ActiveModel = e.into();
+        for path in &paths {
+            match path.as_str() {
+                "name" if patch.name.is_empty() => {
+                    return Err(RsysError::InvalidResource(
+                        "name must not be empty".to_string(),
+                    ))
+                }
+                "name" => e.name = Set(patch.name.clone()),
+                "resource_type" => e.resource_type = Set(patch.resource_type.clone()),
+                "capacity" => {
+                    let capacity = check_capacity(patch.capacity)?;
+                    // the row lock keeps new bookings out while we look
+                    ensure_fits(&txn, &patch.id, capacity).await?;
+                    e.capacity = Set(capacity);
+                }
+                "timezone" => e.timezone = Set(timezone_or_utc(patch.timezone.clone())?),
+                "active" => e.active = Set(patch.active),
+                "metadata" => e.metadata = Set(parse_metadata(&patch.metadata)?),
+                other => return Err(RsysError::InvalidFieldMask(other.to_string())),
+            }
+        }
+        let r = e.update(&txn).await?;
+        txn.commit().await?;
+        Ok(r.into())
+    }
+
+    async fn delete_resource(&self, id: &str) -> Result<Resource, RsysError> {
+        let txn = self.db.begin().await?;
+        let e = Resources::find_by_id(id.to_string())
+            .lock_exclusive()
+            .one(&txn)
+            .await?
+            .ok_or_else(|| RsysError::UnknownResource(id.to_string()))?;
+        let live = Reservations::find()
+            .filter(
+                Condition::all()
+                    .add(reservations::Column::ResourceId.eq(id))
+                    .add(ReservationManager::live()),
+            )
+            .count(&txn)
+            .await?;
+        if live > 0 {
+            return Err(RsysError::ResourceInUse(id.to_string()));
+        }
+        Resources::delete_by_id(id.to_string()).exec(&txn).await?;
+        txn.commit().await?;
+        Ok(e.into())
+    }
+
+    async fn list_resources(&self, include_inactive: bool) -> Result<Vec<Resource>, RsysError> {
+        let mut select = Resources::find().order_by_asc(resources::Column::Id);
+        if !include_inactive {
+            select = select.filter(resources::Column::Active.eq(true));
+        }
+        let rows = select.all(&self.db).await?;
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{env_con_str, generate_random_reservation, generate_random_resource, Caller, Rsvp};
+    use chrono::Duration;
+    use rsys_abi::{convert_to_timestamp, UpdateResourceRequest};
+
+    #[tokio::test]
+    async fn resource_crud() {
+        let rm = ReservationManager::new(env_con_str()).await.unwrap();
+        let mut resource = generate_random_resource();
+        resource.metadata = r#"{"floor": 3}"#.to_string();
+        let created = rm.create_resource(resource.clone()).await.unwrap();
+        assert_eq!(created.timezone, "UTC");
+        assert_eq!(created.metadata, r#"{"floor":3}"#);
+        assert!(rm.create_resource(resource.clone()).await.is_err());
+
+        let mut bad = generate_random_resource();
+        bad.metadata = "[1]".to_string();
+        assert!(matches!(
+            rm.create_resource(bad).await,
+            Err(RsysError::InvalidResource(_))
+        ));
+        let mut bad = generate_random_resource();
+        bad.timezone = "Mars/Olympus_Mons".to_string();
+        assert!(matches!(
+            rm.create_resource(bad).await,
+            Err(RsysError::InvalidResource(_))
+        ));
+        assert!(matches!(
+            rm.update_resource(UpdateResourceRequest::masked(
+                Resource {
+                    id: created.id.clone(),
+                    ..Default::default()
+                },
+                &["name"],
+            ))
+            .await,
+            Err(RsysError::InvalidResource(_))
+        ));
+
+        let mut rsvp = generate_random_reservation();
+        rsvp.resource_id = created.id.clone();
+        let rsvp = rm.create(rsvp).await.unwrap();
+
+        // deactivated resources take no new reservations
+        let updated = rm
+            .update_resource(UpdateResourceRequest::masked(
+                Resource {
+                    id: created.id.clone(),
+                    active: false,
+                    capacity: 5,
+                    ..Default::default()
+                },
+                &["active"],
+            ))
+            .await
+            .unwrap();
+        assert!(!updated.active);
+        assert_eq!(updated.capacity, 1);
+        let mut again = generate_random_reservation();
+        again.resource_id = created.id.clone();
+        assert!(matches!(
+            rm.create(again).await,
+            Err(RsysError::UnknownResource(_))
+        ));
+        let listed = rm.list_resources(false).await.unwrap();
+        assert!(listed.iter().all(|r| r.id != created.id));
+
+        assert!(matches!(
+            rm.delete_resource(&created.id).await,
+            Err(RsysError::ResourceInUse(_))
+        ));
+        rm.cancel(rsvp.id.into(), &Caller::user(&rsvp.uid))
+            .await
+            .unwrap();
+        rm.delete_resource(&created.id).await.unwrap();
+        assert!(matches!(
+            rm.get_resource(&created.id).await,
+            Err(RsysError::UnknownResource(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn capacity_not_below_bookings() {
+        let rm = ReservationManager::new(env_con_str()).await.unwrap();
+        let mut resource = generate_random_resource();
+        resource.capacity = 3;
+        let resource = rm.create_resource(resource).await.unwrap();
+        let mut rsvp = generate_random_reservation();
+        rsvp.resource_id = resource.id.clone();
+        rsvp.start = Some(convert_to_timestamp(Utc::now() + Duration::hours(1)));
+        rsvp.end = Some(convert_to_timestamp(Utc::now() + Duration::hours(2)));
+        rsvp.quantity = 2;
+        rm.create(rsvp).await.unwrap();
+
+        let resize = |capacity| {
+            UpdateResourceRequest::masked(
+                Resource {
+                    id: resource.id.clone(),
+                    capacity,
+                    ..Default::default()
+                },
+                &["capacity"],
+            )
+        };
+        assert!(matches!(
+            rm.update_resource(resize(1)).await,
+            Err(RsysError::ResourceInUse(_))
+        ));
+        assert_eq!(rm.update_resource(resize(2)).await.unwrap().capacity, 2);
+    }
+
+    #[tokio::test]
+    async fn unknown_resource_rejected() {
+        let rm = ReservationManager::new(env_con_str()).await.unwrap();
+        let result = rm.create(generate_random_reservation()).await;
+        assert!(matches!(result, Err(RsysError::UnknownResource(_))));
+    }
+}
@@ -0,0 +1,377 @@
+use chrono::{
+    offset::LocalResult, DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, Offset, TimeZone,
+    Utc, Weekday,
+};
+use chrono_tz::Tz;
+use std::str::FromStr;
+
+use crate::error::RsysError;
+
+/// upper bound on the occurrences one series may expand to
+pub const MAX_OCCURRENCES: usize = 366;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freq {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl Freq {
+    /// largest INTERVAL accepted, a year of days or ten years otherwise
+    fn max_interval(self) -> u32 {
+        match self {
+            Freq::Daily => 366,
+            Freq::Weekly => 520,
+            Freq::Monthly => 120,
+            Freq::Yearly => 10,
+        }
+    }
+}
+
+/// the subset of an iCalendar RRULE (RFC 5545) we support: FREQ, INTERVAL,
+/// COUNT, UNTIL and BYDAY (plain weekdays, DAILY and WEEKLY only)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RRule {
+    pub freq: Freq,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<DateTime<Utc>>,
+    pub by_day: Vec<Weekday>,
+}
+
+impl FromStr for RRule {
+    type Err = RsysError;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let invalid = |why: &str| RsysError::InvalidRRule(format!("{}: {}", rule, why));
+        let body = rule.trim();
+        let body = body.strip_prefix("RRULE:").unwrap_or(body);
+
+        let mut freq = None;
+        let mut interval = 1;
+        let mut count = None;
+        let mut until = None;
+        let mut by_day = vec![];
+        for part in body.split(';').filter(|p| !p.is_empty()) {
+            let (key, value) = part.split_once('=').ok_or_else(|| invalid(part))?;
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Freq::Daily,
+                        "WEEKLY" => Freq::Weekly,
+                        "MONTHLY" => Freq::Monthly,
+                        "YEARLY" => Freq::Yearly,
+                        _ => return Err(invalid("unsupported FREQ")),
+                    })
+                }
+                "INTERVAL" => {
+                    interval = value
+                        .parse()
+                        .ok()
+                        .filter(|i| *i > 0)
+                        .ok_or_else(|| invalid("bad INTERVAL"))?
+                }
+                "COUNT" => {
+                    count = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|c| *c > 0)
+                            .ok_or_else(|| invalid("bad COUNT"))?,
+                    )
+                }
+                "UNTIL" => until = Some(parse_until(value).ok_or_else(|| invalid("bad UNTIL"))?),
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        by_day.push(parse_weekday(day).ok_or_else(|| invalid("bad BYDAY"))?);
+                    }
+                }
+                _ => return Err(invalid("unsupported part")),
+            }
+        }
+
+        let freq = freq.ok_or_else(|| invalid("missing FREQ"))?;
+        if interval > freq.max_interval() {
+            return Err(invalid("INTERVAL too large"));
+        }
+        if count.is_some() == until.is_some() {
+            return Err(invalid("exactly one of COUNT and UNTIL is required"));
+        }
+        if !by_day.is_empty() && !matches!(freq, Freq::Daily | Freq::Weekly) {
+            return Err(invalid("BYDAY is only supported with DAILY and WEEKLY"));
+        }
+        by_day.sort_by_key(|d| d.num_days_from_monday());
+        by_day.dedup();
+        Ok(RRule {
+            freq,
+            interval,
+            count,
+            until,
+            by_day,
+        })
+    }
+}
+
+impl RRule {
+    /// start times of every occurrence, beginning with `start` when it
+    /// matches the rule. days are counted on the calendar of `tz`, so an
+    /// occurrence keeps its wall clock time across DST changes.
+    pub fn occurrences(
+        &self,
+        start: DateTime<Utc>,
+        tz: Tz,
+    ) -> Result<Vec<DateTime<Utc>>, RsysError> {
+        let out_of_range = || RsysError::InvalidRRule("occurrences out of range".to_string());
+        let shift = |at: NaiveDateTime, days: i64| {
+            // `Duration::days` panics past i64::MAX milliseconds
+            (days.abs() <= i64::MAX / 86_400_000)
+                .then(|| Duration::days(days))
+                .and_then(|d| at.checked_add_signed(d))
+                .ok_or_else(out_of_range)
+        };
+        let local = start.with_timezone(&tz).naive_local();
+        let mut found = vec![];
+        let interval = self.interval as i64;
+        // periods without a valid date (Feb 30, ...) are skipped, this bounds
+        // how many we look at before giving up
+        for period in 0..(MAX_OCCURRENCES as i64 * 12) {
+            let mut candidates = match self.freq {
+                Freq::Daily => vec![shift(local, period * interval)?],
+                Freq::Weekly => {
+                    let week = shift(local, period * interval * 7)?;
+                    if self.by_day.is_empty() {
+                        vec![week]
+                    } else {
+                        let monday = shift(week, -(week.weekday().num_days_from_monday() as i64))?;
+                        let mut days = vec![];
+                        for d in &self.by_day {
+                            days.push(shift(monday, d.num_days_from_monday() as i64)?);
+                        }
+                        days
+                    }
+                }
+                Freq::Monthly => add_months(local, period * interval).into_iter().collect(),
+                Freq::Yearly => add_months(local, period * interval * 12)
+                    .into_iter()
+                    .collect(),
+            };
+            if self.freq == Freq::Daily && !self.by_day.is_empty() {
+                candidates.retain(|c| self.by_day.contains(&c.weekday()));
+            }
+
+            let candidates = candidates.into_iter().map(|at| from_local(tz, at));
+            for at in candidates.filter(|at| *at >= start) {
+                if self.until.is_some_and(|until| at > until)
+                    || self
+                        .count
+                        .is_some_and(|count| found.len() >= count as usize)
+                {
+                    return Ok(found);
+                }
+                if found.len() == MAX_OCCURRENCES {
+                    return Err(RsysError::InvalidRRule(format!(
+                        "more than {} occurrences",
+                        MAX_OCCURRENCES
+                    )));
+                }
+                found.push(at);
+            }
+        }
+        Ok(found)
+    }
+}
+
+fn parse_weekday(day: &str) -> Option<Weekday> {
+    match day.trim().to_ascii_uppercase().as_str() {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+/// `20231231T235959Z`, a date alone means the end of that day
+fn parse_until(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(at) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ") {
+        return Some(Utc.from_utc_datetime(&at));
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+    Some(Utc.from_utc_datetime(&date.and_hms_opt(23, 59, 59)?))
+}
+
+/// same day of month `months` later, `None` when that month is too short
+fn add_months(at: NaiveDateTime, months: i64) -> Option<NaiveDateTime> {
+    let total = at.year() as i64 * 12 + at.month0() as i64 + months;
+    let year = i32::try_from(total / 12).ok()?;
+    let date = NaiveDate::from_ymd_opt(year, (total % 12) as u32 + 1, at.day())?;
+    Some(date.and_time(at.time()))
+}
+
+/// the instant `at` names in `tz`. of a time repeated when clocks go back
+/// the first is taken, a time skipped when they go forward is read with the
+/// offset from before the change, as RFC 5545 does.
+fn from_local(tz: Tz, at: NaiveDateTime) -> DateTime<Utc> {
+    match tz.from_local_datetime(&at) {
+        LocalResult::Single(at) | LocalResult::Ambiguous(at, _) => at.with_timezone(&Utc),
+        LocalResult::None => {
+            let before = tz.offset_from_utc_datetime(&(at - Duration::days(1))).fix();
+            Utc.from_utc_datetime(&(at - Duration::seconds(before.local_minus_utc() as i64)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn weekly_by_day() {
+        // 2023-10-17 is a Tuesday
+        let rule: RRule = "RRULE:FREQ=WEEKLY;BYDAY=TU,TH;COUNT=5".parse().unwrap();
+        let found = rule
+            .occurrences(at("2023-10-17T10:00:00Z"), Tz::UTC)
+            .unwrap();
+        assert_eq!(
+            found,
+            vec![
+                at("2023-10-17T10:00:00Z"),
+                at("2023-10-19T10:00:00Z"),
+                at("2023-10-24T10:00:00Z"),
+                at("2023-10-26T10:00:00Z"),
+                at("2023-10-31T10:00:00Z"),
+            ]
+        );
+
+        // days before the first occurrence in its week are not booked
+        let rule: RRule = "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,WE;UNTIL=20231101"
+            .parse()
+            .unwrap();
+        let found = rule
+            .occurrences(at("2023-10-18T10:00:00Z"), Tz::UTC)
+            .unwrap();
+        assert_eq!(
+            found,
+            vec![
+                at("2023-10-18T10:00:00Z"),
+                at("2023-10-30T10:00:00Z"),
+                at("2023-11-01T10:00:00Z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn daily_and_monthly() {
+        let rule: RRule = "FREQ=DAILY;BYDAY=MO,TU,WE,TH,FR;COUNT=3".parse().unwrap();
+        let found = rule
+            .occurrences(at("2023-10-20T09:00:00Z"), Tz::UTC)
+            .unwrap();
+        assert_eq!(
+            found,
+            vec![
+                at("2023-10-20T09:00:00Z"),
+                at("2023-10-23T09:00:00Z"),
+                at("2023-10-24T09:00:00Z"),
+            ]
+        );
+
+        // months without a 31st are skipped
+        let rule: RRule = "FREQ=MONTHLY;COUNT=3".parse().unwrap();
+        let found = rule
+            .occurrences(at("2023-01-31T09:00:00Z"), Tz::UTC)
+            .unwrap();
+        assert_eq!(
+            found,
+            vec![
+                at("2023-01-31T09:00:00Z"),
+                at("2023-03-31T09:00:00Z"),
+                at("2023-05-31T09:00:00Z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_rules() {
+        for rule in [
+            "FREQ=WEEKLY",
+            "COUNT=3",
+            "FREQ=HOURLY;COUNT=3",
+            "FREQ=WEEKLY;COUNT=3;UNTIL=20231101",
+            "FREQ=WEEKLY;BYDAY=1MO;COUNT=3",
+            "FREQ=MONTHLY;BYDAY=MO;COUNT=3",
+            "FREQ=DAILY;INTERVAL=0;COUNT=3",
+            "FREQ=DAILY;BYMONTH=1;COUNT=3",
+        ] {
+            assert!(
+                matches!(rule.parse::<RRule>(), Err(RsysError::InvalidRRule(_))),
+                "{}",
+                rule
+            );
+        }
+
+        let rule: RRule = "FREQ=DAILY;COUNT=1000".parse().unwrap();
+        assert!(rule.occurrences(Utc::now(), Tz::UTC).is_err());
+
+        for rule in [
+            "FREQ=DAILY;INTERVAL=100000000;COUNT=2",
+            "FREQ=WEEKLY;INTERVAL=521;COUNT=2",
+        ] {
+            assert!(
+                matches!(rule.parse::<RRule>(), Err(RsysError::InvalidRRule(_))),
+                "{}",
+                rule
+            );
+        }
+        // the largest intervals still expand far into the future
+        let rule: RRule = "FREQ=YEARLY;INTERVAL=10;COUNT=366".parse().unwrap();
+        assert_eq!(rule.occurrences(Utc::now(), Tz::UTC).unwrap().len(), 366);
+    }
+
+    #[test]
+    fn keeps_local_time_across_dst() {
+        // Berlin leaves summer time on 2023-10-29, 10:00 local moves from
+        // 08:00 to 09:00 UTC
+        let rule: RRule = "FREQ=WEEKLY;BYDAY=TU;COUNT=3".parse().unwrap();
+        let found = rule
+            .occurrences(at("2023-10-24T08:00:00Z"), Tz::Europe__Berlin)
+            .unwrap();
+        assert_eq!(
+            found,
+            vec![
+                at("2023-10-24T08:00:00Z"),
+                at("2023-10-31T09:00:00Z"),
+                at("2023-11-07T09:00:00Z"),
+            ]
+        );
+
+        // 23:30 on Monday in New York is already Tuesday in UTC, BYDAY
+        // matches the local weekday
+        let rule: RRule = "FREQ=DAILY;BYDAY=MO;COUNT=2".parse().unwrap();
+        let found = rule
+            .occurrences(at("2023-10-31T03:30:00Z"), Tz::America__New_York)
+            .unwrap();
+        assert_eq!(
+            found,
+            vec![at("2023-10-31T03:30:00Z"), at("2023-11-07T04:30:00Z")]
+        );
+
+        // 02:30 does not exist on 2024-03-31 in Berlin and is read as 03:30
+        let rule: RRule = "FREQ=DAILY;COUNT=2".parse().unwrap();
+        let found = rule
+            .occurrences(at("2024-03-30T01:30:00Z"), Tz::Europe__Berlin)
+            .unwrap();
+        assert_eq!(
+            found,
+            vec![at("2024-03-30T01:30:00Z"), at("2024-03-31T01:30:00Z")]
+        );
+    }
+}
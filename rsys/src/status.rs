@@ -0,0 +1,88 @@
+use rsys_abi::ReservationStatus;
+
+use crate::error::RsysError;
+
+/// check a reservation may move from `from` to `to`. a new reservation
+/// starts from `Unkown`.
+///
+/// ```text
+/// unknown ──> pending ──> confirmed
+///                │            │
+///                └──> cancelled <──┘
+///
+/// blocked: entered and left by admins only
+/// unknown ──> confirmed: admins only, everyone else starts out pending
+/// ```
+pub fn check_transition(
+    from: ReservationStatus,
+    to: ReservationStatus,
+    admin: bool,
+) -> Result<(), RsysError> {
+    use ReservationStatus::*;
+
+    let allowed = match (from, to) {
+        (Unkown, Pending) => true,
+        (Unkown, Confirmed) => admin,
+        (Pending, Confirmed) => true,
+        (Pending | Confirmed, Cancelled) => true,
+        (Unkown | Pending | Confirmed, Blocked) => admin,
+        (Blocked, Pending | Confirmed | Cancelled) => admin,
+        _ => false,
+    };
+    if allowed {
+        Ok(())
+    } else {
+        Err(RsysError::InvalidTransition { from, to })
+    }
+}
+
+/// status stored in the `r_status` column, anything unrecognised is `Unkown`
+pub fn status_of(value: Option<i32>) -> ReservationStatus {
+    value
+        .and_then(|v| ReservationStatus::try_from(v).ok())
+        .unwrap_or(ReservationStatus::Unkown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ReservationStatus::*;
+
+    #[test]
+    fn user_transitions() {
+        assert!(check_transition(Unkown, Pending, false).is_ok());
+        assert!(check_transition(Pending, Confirmed, false).is_ok());
+        assert!(check_transition(Pending, Cancelled, false).is_ok());
+        assert!(check_transition(Confirmed, Cancelled, false).is_ok());
+
+        assert!(matches!(
+            check_transition(Unkown, Confirmed, false),
+            Err(RsysError::InvalidTransition {
+                from: Unkown,
+                to: Confirmed
+            })
+        ));
+        assert!(check_transition(Unkown, Confirmed, true).is_ok());
+
+        assert!(matches!(
+            check_transition(Confirmed, Pending, false),
+            Err(RsysError::InvalidTransition {
+                from: Confirmed,
+                to: Pending
+            })
+        ));
+        assert!(check_transition(Confirmed, Confirmed, false).is_err());
+        assert!(check_transition(Cancelled, Pending, false).is_err());
+        assert!(check_transition(Cancelled, Confirmed, true).is_err());
+        assert!(check_transition(Pending, Unkown, true).is_err());
+    }
+
+    #[test]
+    fn blocked_is_admin_only() {
+        assert!(check_transition(Pending, Blocked, false).is_err());
+        assert!(check_transition(Pending, Blocked, true).is_ok());
+        assert!(check_transition(Blocked, Cancelled, false).is_err());
+        assert!(check_transition(Blocked, Cancelled, true).is_ok());
+        assert!(check_transition(Blocked, Confirmed, true).is_ok());
+    }
+}
@@ -0,0 +1,270 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use rsys_abi::{DateTimeOffset, OperateType, Reservation, ReservationStatus, WaitlistEntry};
+use sea_orm::{
+    sea_query::{LockBehavior, LockType},
+    ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, ModelTrait, QueryFilter,
+    QueryOrder, QuerySelect, Set, TransactionTrait,
+};
+use sqlx::types::Uuid;
+
+use crate::{
+    availability::Interval, entities::waitlist, error::RsysError, resource,
+    try_convert_to_datetime, Caller, ReservationManager, Waitlist,
+};
+
+/// the pending reservation `entry` turns into when promoted
+fn as_reservation(entry: &waitlist::Model) -> Reservation {
+    Reservation {
+        uid: entry.user_id.clone(),
+        resource_id: entry.resource_id.clone(),
+        note: entry.note.clone().unwrap_or_default(),
+        start: Some(DateTimeOffset(entry.start_time).into()),
+        end: Some(DateTimeOffset(entry.end_time).into()),
+        rstatus: ReservationStatus::Pending as i32,
+        quantity: entry.quantity,
+        ..Default::default()
+    }
+}
+
+/// promote entries waiting on `resource_id` that overlap `window` into
+/// pending reservations, highest priority first, as long as they fit.
+/// runs in the transaction that freed the capacity so both commit together.
+pub(crate) async fn promote(
+    txn: &DatabaseTransaction,
+    resource_id: &str,
+    window: Interval,
+) -> Result<Vec<Uuid>, RsysError> {
+    // a deactivated resource takes no reservations, its entries keep waiting
+    match resource::active(txn, resource_id).await {
+        Err(RsysError::UnknownResource(_)) => return Ok(vec![]),
+        other => other?,
+    };
+    let (start, end) = window;
+    // entries another transaction is promoting are left to it
+    let waiting = waitlist::Entity::find()
+        .filter(waitlist::Column::ResourceId.eq(resource_id))
+        .filter(waitlist::Column::ReservationId.is_null())
+        .filter(waitlist::Column::StartTime.lt(end))
+        .filter(waitlist::Column::EndTime.gt(start))
+        .filter(waitlist::Column::StartTime.gt(Utc::now()))
+        .order_by_desc(waitlist::Column::Priority)
+        .order_by_asc(waitlist::Column::CreatedAt)
+        .order_by_asc(waitlist::Column::Id)
+        .lock_with_behavior(LockType::Update, LockBehavior::SkipLocked)
+        .all(txn)
+        .await?;
+
+    let mut promoted = vec![];
+    for entry in waiting {
+        let savepoint = txn.begin().await?;
+        let id = match ReservationManager::insert_reservation(
+            &savepoint,
+            &as_reservation(&entry),
+            OperateType::Promote,
+            None,
+        )
+        .await
+        {
+            Ok(id) => id,
+            Err(RsysError::AlreadyBooked(_)) => {
+                savepoint.rollback().await?;
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        savepoint.commit().await?;
+
+        let mut entry: waitlist::ActiveModel = entry.into();
+        entry.reservation_id = Set(Some(id));
+        entry.update(txn).await?;
+        promoted.push(id);
+    }
+    Ok(promoted)
+}
+
+#[async_trait]
+impl Waitlist for ReservationManager {
+    async fn join_waitlist(
+        &self,
+        entry: WaitlistEntry,
+        caller: &Caller,
+    ) -> Result<WaitlistEntry, RsysError> {
+        let (Some(start), Some(end)) = (entry.start, entry.end) else {
+            return Err(RsysError::InvalidTimespan);
+        };
+        let (start, end) = (
+            try_convert_to_datetime(start)?,
+            try_convert_to_datetime(end)?,
+        );
+        if start >= end {
+            return Err(RsysError::InvalidTimespan);
+        }
+        if start <= Utc::now() {
+            return Err(RsysError::InvalidWaitlistEntry(
+                "the slot has already started".to_string(),
+            ));
+        }
+        let quantity = entry.quantity.max(1);
+
+        let txn = self.db.begin().await?;
+        let resource = resource::bookable(&txn, &entry.resource_id).await?;
+        if quantity > resource.capacity {
+            return Err(RsysError::InvalidQuantity(quantity));
+        }
+        let e = waitlist::ActiveModel {
+            user_id: Set(entry.uid),
+            resource_id: Set(entry.resource_id),
+            start_time: Set(start),
+            end_time: Set(end),
+            quantity: Set(quantity),
+            note: Set(Some(entry.note).filter(|n| !n.is_empty())),
+            // only admins may jump the queue
+            priority: Set(if caller.admin { entry.priority } else { 0 }),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        promote(&txn, &e.resource_id, (start, end)).await?;
+        let e = waitlist::Entity::find_by_id(e.id)
+            .one(&txn)
+            .await?
+            .ok_or_else(|| RsysError::UnknownWaitlistEntry(e.id.to_string()))?;
+        txn.commit().await?;
+        Ok(e.into())
+    }
+
+    async fn leave_waitlist(&self, id: &str, caller: &Caller) -> Result<WaitlistEntry, RsysError> {
+        let unknown = || RsysError::UnknownWaitlistEntry(id.to_string());
+        let uuid = Uuid::parse_str(id).map_err(|_| unknown())?;
+        let txn = self.db.begin().await?;
+        let e = waitlist::Entity::find_by_id(uuid)
+            .lock_exclusive()
+            .one(&txn)
+            .await?
+            .ok_or_else(unknown)?;
+        caller.check_access(Some(&e.user_id))?;
+        e.clone().delete(&txn).await?;
+        txn.commit().await?;
+        Ok(e.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entities::{prelude::ReservationChanges, reservation_changes},
+        env_con_str, generate_random_resource, generate_random_string, ResourceRegistry, Rsvp,
+    };
+    use chrono::Duration;
+    use rsys_abi::{convert_to_timestamp, CancelRequest, GetRequest};
+
+    #[tokio::test]
+    async fn promoted_on_cancel() {
+        let rm = ReservationManager::new(env_con_str()).await.unwrap();
+        let room = rm
+            .create_resource(generate_random_resource())
+            .await
+            .unwrap()
+            .id;
+        let start = Utc::now() + Duration::days(3);
+        let end = start + Duration::hours(1);
+        let booked = rm
+            .create(Reservation::new_pending(
+                generate_random_string(7),
+                room.clone(),
+                "",
+                start,
+                end,
+            ))
+            .await
+            .unwrap();
+        let entry = |priority: i32| WaitlistEntry {
+            uid: generate_random_string(7),
+            resource_id: room.clone(),
+            start: Some(convert_to_timestamp(start + Duration::minutes(30))),
+            end: Some(convert_to_timestamp(end + Duration::minutes(30))),
+            priority,
+            ..Default::default()
+        };
+
+        let user = |entry: &WaitlistEntry| Caller::user(&entry.uid);
+        let admin = Caller::admin("admin");
+        let first = entry(9);
+        let first = rm
+            .join_waitlist(first.clone(), &user(&first))
+            .await
+            .unwrap();
+        assert_eq!(first.priority, 0);
+        let urgent = rm.join_waitlist(entry(5), &admin).await.unwrap();
+        assert_eq!(urgent.priority, 5);
+        assert!(first.reservation_id.is_empty() && urgent.reservation_id.is_empty());
+        let mut past = entry(0);
+        past.start = Some(convert_to_timestamp(Utc::now() - Duration::hours(1)));
+        assert!(matches!(
+            rm.join_waitlist(past, &admin).await,
+            Err(RsysError::InvalidWaitlistEntry(_))
+        ));
+        let mut far = entry(0);
+        far.end = Some(prost_types::Timestamp {
+            seconds: i64::MAX,
+            nanos: 0,
+        });
+        assert!(matches!(
+            rm.join_waitlist(far, &admin).await,
+            Err(RsysError::InvalidTime(_))
+        ));
+
+        // the higher priority goes first, the other one no longer fits
+        let owner = Caller::user(&booked.uid);
+        rm.cancel(CancelRequest::from(booked.id), &owner)
+            .await
+            .unwrap();
+        let urgent_owner = Caller::user(&urgent.uid);
+        assert!(matches!(
+            rm.leave_waitlist(&urgent.id, &owner).await,
+            Err(RsysError::PermissionDenied(_))
+        ));
+        let promoted = rm.leave_waitlist(&urgent.id, &urgent_owner).await.unwrap();
+        assert!(!promoted.reservation_id.is_empty());
+        let rsvp = rm
+            .get(
+                GetRequest {
+                    id: promoted.reservation_id.clone(),
+                },
+                &urgent_owner,
+            )
+            .await
+            .unwrap();
+        assert_eq!(rsvp.uid, urgent.uid);
+        assert_eq!(rsvp.rstatus, ReservationStatus::Pending as i32);
+        let change = ReservationChanges::find()
+            .filter(
+                reservation_changes::Column::ReservationId.eq(Uuid::parse_str(&rsvp.id).unwrap()),
+            )
+            .one(&rm.db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(change.op, Some(OperateType::Promote as i32));
+
+        let waiting = rm.leave_waitlist(&first.id, &admin).await.unwrap();
+        assert!(waiting.reservation_id.is_empty());
+        assert!(matches!(
+            rm.leave_waitlist(&first.id, &admin).await,
+            Err(RsysError::UnknownWaitlistEntry(_))
+        ));
+
+        // nothing to wait for on a free slot
+        let mut later = entry(0);
+        later.start = Some(convert_to_timestamp(start + Duration::days(1)));
+        later.end = Some(convert_to_timestamp(end + Duration::days(1)));
+        assert!(!rm
+            .join_waitlist(later, &admin)
+            .await
+            .unwrap()
+            .reservation_id
+            .is_empty());
+    }
+}
@@ -0,0 +1,313 @@
+use jsonwebtoken::{errors::ErrorKind, Algorithm, DecodingKey, Validation};
+use rsys::{error::RsysError, Caller};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tonic::{service::Interceptor, Request, Status};
+
+use crate::{config::AuthConfig, error::ServError};
+
+/// the claims read from a bearer token
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Claims {
+    /// the uid the caller acts as
+    pub sub: String,
+    /// has to match `auth.issuer`
+    pub iss: String,
+    /// has to match `auth.audience`
+    pub aud: String,
+    /// expiry in seconds since the epoch
+    pub exp: i64,
+    /// seconds since the epoch the token is valid from
+    pub nbf: i64,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+struct Keys {
+    hs256: Option<DecodingKey>,
+    rs256: Option<DecodingKey>,
+    admin_role: String,
+    issuer: String,
+    audience: String,
+}
+
+impl Keys {
+    /// the key and checks of tokens signed with `alg`, a validation only ever
+    /// accepts the one algorithm it was made for
+    fn validation(&self, alg: Algorithm) -> Option<(&DecodingKey, Validation)> {
+        let key = match alg {
+            Algorithm::HS256 => self.hs256.as_ref()?,
+            Algorithm::RS256 => self.rs256.as_ref()?,
+            _ => return None,
+        };
+        let mut validation = Validation::new(alg);
+        validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud", "sub"]);
+        validation.validate_nbf = true;
+        validation.leeway = 0;
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        Some((key, validation))
+    }
+}
+
+/// checks the `authorization: Bearer <jwt>` metadata of every request and
+/// stores the caller it names in the request extensions. without a token, a
+/// client certificate verified over mutual TLS stands for the user named by
+/// its common name.
+#[derive(Clone)]
+pub struct Authenticator {
+    keys: Arc<Keys>,
+}
+
+impl Authenticator {
+    pub fn new(config: &AuthConfig) -> Result<Self, ServError> {
+        let invalid = |why: &str| ServError(RsysError::ConfigError(format!("auth: {}", why)));
+        let rs256 = match &config.rs256_public_key {
+            Some(pem) => Some(
+                DecodingKey::from_rsa_pem(pem.as_bytes())
+                    .map_err(|_| invalid("rs256_public_key is not a PEM public key"))?,
+            ),
+            None => None,
+        };
+        let hs256 = config
+            .hs256_secret
+            .as_ref()
+            .map(|secret| DecodingKey::from_secret(secret.as_bytes()));
+        if hs256.is_none() && rs256.is_none() {
+            return Err(invalid("no hs256_secret or rs256_public_key"));
+        }
+        Ok(Authenticator {
+            keys: Arc::new(Keys {
+                hs256,
+                rs256,
+                admin_role: config.admin_role.clone(),
+                issuer: config.issuer.clone(),
+                audience: config.audience.clone(),
+            }),
+        })
+    }
+
+    /// the caller a signed token, valid right now and meant for us, stands
+    /// for
+    pub fn verify(&self, token: &str) -> Result<Caller, RsysError> {
+        let invalid = || RsysError::Unauthenticated("invalid token".to_string());
+        let header = jsonwebtoken::decode_header(token).map_err(|_| invalid())?;
+        let (key, validation) = self.keys.validation(header.alg).ok_or_else(invalid)?;
+        let claims = match jsonwebtoken::decode::<Claims>(token, key, &validation) {
+            Ok(data) => data.claims,
+            Err(err) if *err.kind() == ErrorKind::ExpiredSignature => {
+                return Err(RsysError::Unauthenticated("token expired".to_string()))
+            }
+            Err(_) => return Err(invalid()),
+        };
+        if claims.sub.is_empty() {
+            return Err(invalid());
+        }
+        let admin = claims.roles.iter().any(|r| *r == self.keys.admin_role);
+        Ok(Caller {
+            uid: claims.sub,
+            admin,
+        })
+    }
+}
+
+impl Interceptor for Authenticator {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let cert = request.peer_certs().and_then(|certs| {
+            certs
+                .first()
+                .and_then(|c| ClientCert::from_der(c.get_ref()))
+        });
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        let caller = match (token, &cert) {
+            (Some(token), _) => self.verify(token).map_err(ServError)?,
+            (None, Some(cert)) if !cert.common_name.is_empty() => {
+                Caller::user(cert.common_name.clone())
+            }
+            _ => return Err(Status::unauthenticated("missing bearer token")),
+        };
+        request.extensions_mut().insert(caller);
+        if let Some(cert) = cert {
+            request.extensions_mut().insert(cert);
+        }
+        Ok(request)
+    }
+}
+
+/// the certificate a client presented over mutual TLS, only ever built from
+/// one the server verified against `client_ca`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCert {
+    /// the whole subject, e.g. `CN=alice, O=example`
+    pub subject: String,
+    /// empty if the subject has no common name
+    pub common_name: String,
+}
+
+impl ClientCert {
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+        let subject = cert.subject();
+        let common_name = subject
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .unwrap_or_default();
+        Some(ClientCert {
+            subject: subject.to_string(),
+            common_name: common_name.to_string(),
+        })
+    }
+}
+
+/// the caller `Authenticator` stored on `request`
+pub(crate) fn caller_of<T>(request: &Request<T>) -> Result<Caller, ServError> {
+    request
+        .extensions()
+        .get::<Caller>()
+        .cloned()
+        .ok_or_else(|| ServError(RsysError::Unauthenticated("no caller".to_string())))
+}
+
+/// book in the caller's name when `uid` is empty, only admins may book for
+/// someone else
+pub(crate) fn claim(caller: &Caller, uid: &mut String) -> Result<(), ServError> {
+    if uid.is_empty() {
+        *uid = caller.uid.clone();
+    }
+    caller.check_access(Some(uid)).map_err(ServError)
+}
+
+/// registering and changing resources is left to admins
+pub(crate) fn require_admin(caller: &Caller) -> Result<(), ServError> {
+    if caller.admin {
+        return Ok(());
+    }
+    Err(ServError(RsysError::PermissionDenied(format!(
+        "{} is not an admin",
+        caller.uid
+    ))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use jsonwebtoken::{EncodingKey, Header};
+    use rsa::{
+        pkcs8::{EncodePrivateKey, EncodePublicKey, LineEnding},
+        RsaPrivateKey,
+    };
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn now() -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64
+    }
+
+    fn claims(exp_in: i64, roles: &[&str]) -> Claims {
+        Claims {
+            sub: "alice".to_string(),
+            iss: "rsys".to_string(),
+            aud: "rsys".to_string(),
+            exp: now() + exp_in,
+            nbf: now(),
+            roles: roles.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    fn encode_hs256(claims: &Claims, secret: &[u8]) -> String {
+        jsonwebtoken::encode(
+            &Header::default(),
+            claims,
+            &EncodingKey::from_secret(secret),
+        )
+        .unwrap()
+    }
+
+    fn config(secret: Option<&str>, public_key: Option<String>) -> AuthConfig {
+        AuthConfig {
+            hs256_secret: secret.map(str::to_string),
+            rs256_public_key: public_key,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn hs256_tokens() {
+        let auth = Authenticator::new(&config(Some("secret"), None)).unwrap();
+        let token = encode_hs256(&claims(60, &[]), b"secret");
+        assert_eq!(auth.verify(&token).unwrap(), Caller::user("alice"));
+        let token = encode_hs256(&claims(60, &["admin"]), b"secret");
+        assert_eq!(auth.verify(&token).unwrap(), Caller::admin("alice"));
+
+        assert!(auth
+            .verify(&encode_hs256(&claims(60, &[]), b"other"))
+            .is_err());
+        assert!(auth
+            .verify(&encode_hs256(&claims(-1, &[]), b"secret"))
+            .is_err());
+
+        // a changed payload no longer matches the signature
+        let token = encode_hs256(&claims(60, &[]), b"secret");
+        let forged = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims(60, &["admin"])).unwrap());
+        let parts: Vec<_> = token.split('.').collect();
+        assert!(auth
+            .verify(&format!("{}.{}.{}", parts[0], forged, parts[2]))
+            .is_err());
+        let none = URL_SAFE_NO_PAD.encode(r#"{"alg":"none"}"#);
+        assert!(auth.verify(&format!("{}.{}.", none, parts[1])).is_err());
+    }
+
+    #[test]
+    fn claims_are_checked() {
+        let auth = Authenticator::new(&config(Some("secret"), None)).unwrap();
+        let verify = |claims: Claims| auth.verify(&encode_hs256(&claims, b"secret"));
+
+        let mut early = claims(600, &[]);
+        early.nbf = now() + 300;
+        assert!(verify(early).is_err());
+        let mut elsewhere = claims(60, &[]);
+        elsewhere.iss = "someone-else".to_string();
+        assert!(verify(elsewhere).is_err());
+        let mut other = claims(60, &[]);
+        other.aud = "another-service".to_string();
+        assert!(verify(other).is_err());
+
+        // a token leaving out nbf is refused, too
+        let token = jsonwebtoken::encode(
+            &Header::default(),
+            &serde_json::json!({"sub": "alice", "iss": "rsys", "aud": "rsys", "exp": now() + 60}),
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+        assert!(auth.verify(&token).is_err());
+    }
+
+    #[test]
+    fn rs256_tokens() {
+        let private = RsaPrivateKey::new(&mut rand::thread_rng(), 2048).unwrap();
+        let pem = private
+            .to_public_key()
+            .to_public_key_pem(LineEnding::LF)
+            .unwrap();
+        let auth = Authenticator::new(&config(None, Some(pem))).unwrap();
+
+        let key =
+            EncodingKey::from_rsa_pem(private.to_pkcs8_pem(LineEnding::LF).unwrap().as_bytes())
+                .unwrap();
+        let token =
+            jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims(60, &[]), &key).unwrap();
+        assert_eq!(auth.verify(&token).unwrap(), Caller::user("alice"));
+
+        // no HS256 secret configured, so those are refused
+        assert!(auth.verify(&encode_hs256(&claims(60, &[]), b"")).is_err());
+        assert!(Authenticator::new(&config(None, None)).is_err());
+    }
+}
@@ -1,41 +1,524 @@
+use clap::Parser;
+use rsys::error::RsysError;
+use rsys::DbOptions;
 use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use tokio::fs;
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+use tracing_subscriber::EnvFilter;
 
 use crate::error::ServError;
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// file read when `--config` is not given, skipped if it does not exist
+const DEFAULT_FILE: &str = "config.yml";
+
+/// prefix of environment overrides, `RSYS_SERVER__PORT` sets `server.port`
+const ENV_PREFIX: &str = "RSYS_";
+
+/// command line of the server. settings are layered as defaults, then the
+/// config file, then `DATABASE_URL`, then `RSYS_*` variables, then `--set`.
+#[derive(Debug, Clone, Default, Parser)]
+#[command(about = "reservation service")]
+pub struct Args {
+    /// YAML or TOML file, picked by extension
+    #[arg(short, long, env = "RSYS_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// override a single setting, e.g. `--set server.port=50052`
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub overrides: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub db: DbConfig,
     pub server: ServerConfig,
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub holds: HoldConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub log: LogConfig,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// connection pool of the database, `Debug` masks the password of `url`
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct DbConfig {
     pub url: String,
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub connect_timeout_secs: u64,
+    /// longest a call waits for a free connection
+    pub acquire_timeout_secs: u64,
+    /// idle connections above `min_connections` are closed after this
+    pub idle_timeout_secs: u64,
+    /// postgres aborts statements running longer, 0 turns it off
+    pub statement_timeout_ms: u64,
+    /// attempts after a failed connect on startup, backing off from
+    /// `retry_backoff_ms` and doubling each time
+    pub connect_retries: u32,
+    pub retry_backoff_ms: u64,
+}
+
+impl Default for DbConfig {
+    fn default() -> Self {
+        DbConfig {
+            url: String::new(),
+            max_connections: 10,
+            min_connections: 0,
+            connect_timeout_secs: 10,
+            acquire_timeout_secs: 30,
+            idle_timeout_secs: 600,
+            statement_timeout_ms: 0,
+            connect_retries: 5,
+            retry_backoff_ms: 500,
+        }
+    }
+}
+
+impl fmt::Debug for DbConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DbConfig")
+            .field("url", &rsys::redact_url(&self.url))
+            .field("max_connections", &self.max_connections)
+            .field("min_connections", &self.min_connections)
+            .field("connect_timeout_secs", &self.connect_timeout_secs)
+            .field("acquire_timeout_secs", &self.acquire_timeout_secs)
+            .field("idle_timeout_secs", &self.idle_timeout_secs)
+            .field("statement_timeout_ms", &self.statement_timeout_ms)
+            .field("connect_retries", &self.connect_retries)
+            .field("retry_backoff_ms", &self.retry_backoff_ms)
+            .finish()
+    }
+}
+
+impl DbConfig {
+    pub fn options(&self) -> DbOptions {
+        DbOptions {
+            url: self.url.clone(),
+            max_connections: self.max_connections,
+            min_connections: self.min_connections,
+            connect_timeout: Duration::from_secs(self.connect_timeout_secs),
+            acquire_timeout: Duration::from_secs(self.acquire_timeout_secs),
+            idle_timeout: Duration::from_secs(self.idle_timeout_secs),
+            statement_timeout: Duration::from_millis(self.statement_timeout_ms),
+            connect_retries: self.connect_retries,
+            retry_backoff: Duration::from_millis(self.retry_backoff_ms),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
+    /// serve TLS instead of plaintext HTTP/2 when set
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+}
+
+/// paths of the PEM files TLS is set up from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// certificate chain presented to clients
+    pub cert: String,
+    /// private key of `cert`
+    pub key: String,
+    /// CA client certificates are verified against. once set, clients have to
+    /// present a certificate signed by it unless `client_auth_optional` is set
+    #[serde(default)]
+    pub client_ca: Option<String>,
+    #[serde(default)]
+    pub client_auth_optional: bool,
+}
+
+/// keys bearer tokens are checked with, at least one is required. the
+/// token's `alg` header picks which one. `Debug` leaves out the secret.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    /// shared secret of HS256 tokens
+    #[serde(default)]
+    pub hs256_secret: Option<String>,
+    /// PEM encoded public key (SPKI) of RS256 tokens
+    #[serde(default)]
+    pub rs256_public_key: Option<String>,
+    /// entry of the token's `roles` claim that makes the caller an admin
+    #[serde(default = "default_admin_role")]
+    pub admin_role: String,
+    /// the `iss` claim every token has to carry
+    #[serde(default = "default_token_party")]
+    pub issuer: String,
+    /// the `aud` claim every token has to carry
+    #[serde(default = "default_token_party")]
+    pub audience: String,
+}
+
+fn default_admin_role() -> String {
+    "admin".to_string()
+}
+
+fn default_token_party() -> String {
+    "rsys".to_string()
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            host: "0.0.0.0".to_string(),
+            port: 50051,
+            tls: None,
+        }
+    }
+}
+
+impl fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthConfig")
+            .field("hs256_secret", &self.hs256_secret.as_ref().map(|_| "***"))
+            .field("rs256_public_key", &self.rs256_public_key)
+            .field("admin_role", &self.admin_role)
+            .field("issuer", &self.issuer)
+            .field("audience", &self.audience)
+            .finish()
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            hs256_secret: None,
+            rs256_public_key: None,
+            admin_role: default_admin_role(),
+            issuer: default_token_party(),
+            audience: default_token_party(),
+        }
+    }
+}
+
+/// how often expired holds are swept and how many are cancelled per sweep
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HoldConfig {
+    #[serde(default = "default_sweep_interval_secs")]
+    pub sweep_interval_secs: u64,
+    #[serde(default = "default_sweep_batch")]
+    pub batch: u64,
+}
+
+fn default_sweep_interval_secs() -> u64 {
+    30
+}
+
+fn default_sweep_batch() -> u64 {
+    100
+}
+
+impl Default for HoldConfig {
+    fn default() -> Self {
+        HoldConfig {
+            sweep_interval_secs: default_sweep_interval_secs(),
+            batch: default_sweep_batch(),
+        }
+    }
+}
+
+/// where Prometheus scrapes `/metrics` from, plain HTTP on its own port
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    pub host: String,
+    /// 0 picks a free port, see `ServerHandle::metrics_addr`
+    pub port: u16,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            enabled: true,
+            host: "0.0.0.0".to_string(),
+            port: 9100,
+        }
+    }
+}
+
+/// what is logged, how, and where spans are exported to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// `RUST_LOG` style directives, e.g. `info,rsys=debug`
+    pub filter: String,
+    pub format: LogFormat,
+    /// export spans over OTLP/gRPC when set
+    pub otlp: Option<OtlpConfig>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            filter: "info".to_string(),
+            format: LogFormat::Text,
+            otlp: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// human readable lines
+    #[default]
+    Text,
+    /// one JSON object per line
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OtlpConfig {
+    /// collector address, e.g. `http://localhost:4317`
+    pub endpoint: String,
+    /// `service.name` the spans are reported under
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+}
+
+fn default_service_name() -> String {
+    "rsys".to_string()
+}
+
+impl TlsConfig {
+    /// read the files this points at
+    pub async fn load(&self) -> Result<ServerTlsConfig, ServError> {
+        let cert = read_pem(&self.cert).await?;
+        let key = read_pem(&self.key).await?;
+        let mut tls = ServerTlsConfig::new().identity(Identity::from_pem(cert, key));
+        if let Some(ca) = &self.client_ca {
+            tls = tls
+                .client_ca_root(Certificate::from_pem(read_pem(ca).await?))
+                .client_auth_optional(self.client_auth_optional);
+        }
+        Ok(tls)
+    }
+}
+
+async fn read_pem(path: &str) -> Result<Vec<u8>, ServError> {
+    fs::read(path).await.map_err(|_| {
+        ServError(rsys::error::RsysError::ConfigError(format!(
+            "tls: cannot read {}",
+            path
+        )))
+    })
+}
+
+fn invalid(key: impl Into<String>, reason: impl ToString) -> ServError {
+    ServError(RsysError::InvalidConfig {
+        key: key.into(),
+        reason: reason.to_string(),
+    })
+}
+
+/// a YAML or TOML file as a tree
+async fn read_file(path: &Path) -> Result<Value, ServError> {
+    let key = path.display().to_string();
+    let text = fs::read_to_string(path)
+        .await
+        .map_err(|err| invalid(&key, err))?;
+    if path.extension().is_some_and(|ext| ext == "toml") {
+        return toml::from_str(&text).map_err(|err| invalid(key, err.message()));
+    }
+    serde_yaml::from_str(&text).map_err(|err| invalid(key, err))
+}
+
+/// `over` on top of `base`, sections are merged key by key
+fn merge(base: &mut Value, over: Value) {
+    match (base, over) {
+        (Value::Mapping(base), Value::Mapping(over)) => {
+            for (k, v) in over {
+                match base.get_mut(&k) {
+                    Some(existing) => merge(existing, v),
+                    None => {
+                        base.insert(k, v);
+                    }
+                }
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
+
+/// set the dotted `key` of `tree` to `raw`. settings that are numbers or
+/// booleans already are parsed as such, anything else stays a string unless
+/// it is `true` or `false`.
+fn set(tree: &mut Value, key: &str, raw: &str) -> Result<(), ServError> {
+    let mut node = tree;
+    let mut path = key.split('.').peekable();
+    while let Some(part) = path.next() {
+        if part.is_empty() {
+            return Err(invalid(key, "empty key"));
+        }
+        if node.is_null() {
+            *node = Value::Mapping(Mapping::new());
+        }
+        let Value::Mapping(section) = node else {
+            return Err(invalid(key, "is not a section"));
+        };
+        let part = Value::String(part.to_string());
+        if path.peek().is_some() {
+            node = section.entry(part).or_insert(Value::Null);
+            continue;
+        }
+        let value = match section.get(&part) {
+            Some(Value::Number(_) | Value::Bool(_)) => {
+                serde_yaml::from_str(raw).map_err(|err| invalid(key, err))?
+            }
+            _ if raw == "true" || raw == "false" => Value::Bool(raw == "true"),
+            _ => Value::String(raw.to_string()),
+        };
+        section.insert(part, value);
+        return Ok(());
+    }
+    Err(invalid(key, "empty key"))
 }
 
 impl Config {
-    #[allow(dead_code)]
+    /// `path` on top of the defaults, without any overrides
     pub async fn load(path: &str) -> Result<Self, ServError> {
-        let config = fs::read_to_string(&path).await;
-        if config.is_err() {
-            return Err(ServError(rsys::error::RsysError::ConfigError(
-                path.to_string(),
-            )));
+        let mut tree = Self::defaults()?;
+        merge(&mut tree, read_file(Path::new(path)).await?);
+        Self::from_tree(tree)
+    }
+
+    /// every layer of `args`, with overrides taken from the process
+    /// environment and `.env`
+    pub async fn layered(args: &Args) -> Result<Self, ServError> {
+        let mut env: Vec<_> = std::env::vars().collect();
+        if let Ok(url) = dotenvy::var("DATABASE_URL") {
+            env.push(("DATABASE_URL".to_string(), url));
         }
-        let config = serde_yaml::from_str(&config.unwrap());
-        if config.is_err() {
-            return Err(ServError(rsys::error::RsysError::ConfigError(
-                path.to_string(),
-            )));
+        Self::layered_with(args, env).await
+    }
+
+    async fn layered_with(
+        args: &Args,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, ServError> {
+        let mut tree = Self::defaults()?;
+        match &args.config {
+            Some(path) => merge(&mut tree, read_file(path).await?),
+            None if Path::new(DEFAULT_FILE).exists() => {
+                merge(&mut tree, read_file(Path::new(DEFAULT_FILE)).await?)
+            }
+            None => {}
+        }
+
+        // `DATABASE_URL` is what sqlx and the migrations read, a more
+        // specific `RSYS_DB__URL` still wins over it
+        let mut overrides = vec![];
+        for (name, value) in env {
+            if name == "DATABASE_URL" {
+                set(&mut tree, "db.url", &value)?;
+            } else if let Some(key) = name.strip_prefix(ENV_PREFIX) {
+                if key != "CONFIG" {
+                    overrides.push((key.to_lowercase().replace("__", "."), value));
+                }
+            }
         }
-        Ok(config.unwrap())
+        // parents before their children, so `RSYS_SERVER__TLS` can not wipe
+        // out `RSYS_SERVER__TLS__CERT` depending on the environment's order
+        overrides.sort();
+        for (key, value) in overrides {
+            set(&mut tree, &key, &value)?;
+        }
+
+        for arg in &args.overrides {
+            let Some((key, value)) = arg.split_once('=') else {
+                return Err(invalid(arg, "expected KEY=VALUE"));
+            };
+            set(&mut tree, key.trim(), value)?;
+        }
+        Self::from_tree(tree)
+    }
+
+    fn defaults() -> Result<Value, ServError> {
+        serde_yaml::to_value(Config::default()).map_err(|err| invalid("", err))
+    }
+
+    fn from_tree(tree: Value) -> Result<Self, ServError> {
+        let config: Config = serde_path_to_error::deserialize(tree)
+            .map_err(|err| invalid(err.path().to_string(), err.inner()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// checks serde can not express, each naming the setting at fault
+    pub fn validate(&self) -> Result<(), ServError> {
+        if self.db.url.is_empty() {
+            return Err(invalid("db.url", "is required"));
+        }
+        if self.db.max_connections == 0 {
+            return Err(invalid("db.max_connections", "must be positive"));
+        }
+        if self.db.min_connections > self.db.max_connections {
+            return Err(invalid(
+                "db.min_connections",
+                "is more than db.max_connections",
+            ));
+        }
+        if self.db.acquire_timeout_secs == 0 {
+            return Err(invalid("db.acquire_timeout_secs", "must be positive"));
+        }
+        if self.db.connect_timeout_secs == 0 {
+            return Err(invalid("db.connect_timeout_secs", "must be positive"));
+        }
+        if self.server.host.is_empty() {
+            return Err(invalid("server.host", "is required"));
+        }
+        if let Some(tls) = &self.server.tls {
+            if tls.cert.is_empty() {
+                return Err(invalid("server.tls.cert", "is required"));
+            }
+            if tls.key.is_empty() {
+                return Err(invalid("server.tls.key", "is required"));
+            }
+        }
+        if self.auth.hs256_secret.is_none() && self.auth.rs256_public_key.is_none() {
+            return Err(invalid("auth", "set hs256_secret or rs256_public_key"));
+        }
+        if self.auth.issuer.is_empty() {
+            return Err(invalid("auth.issuer", "is required"));
+        }
+        if self.auth.audience.is_empty() {
+            return Err(invalid("auth.audience", "is required"));
+        }
+        if self.holds.sweep_interval_secs == 0 {
+            return Err(invalid("holds.sweep_interval_secs", "must be positive"));
+        }
+        if self.holds.batch == 0 {
+            return Err(invalid("holds.batch", "must be positive"));
+        }
+        if self.metrics.enabled && self.metrics.host.is_empty() {
+            return Err(invalid("metrics.host", "is required"));
+        }
+        if let Err(err) = EnvFilter::try_new(&self.log.filter) {
+            return Err(invalid("log.filter", err));
+        }
+        if let Some(otlp) = &self.log.otlp {
+            if otlp.endpoint.is_empty() {
+                return Err(invalid("log.otlp.endpoint", "is required"));
+            }
+        }
+        Ok(())
     }
 }
 
@@ -47,10 +530,178 @@ mod tests {
     async fn load_config() {
         let _path = include_str!("../../config.yml");
         let config = Config::load("../config.yml").await;
+        #[allow(clippy::unnecessary_unwrap)]
         if config.is_err() {
             println!("{:?}", config.err());
         } else {
             println!("{:?}", config.unwrap());
         }
     }
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    /// `config.yml` ships without a secret, tests bring their own
+    const SECRET: &str = "auth.hs256_secret=test-secret";
+
+    fn args(overrides: &[&str]) -> Args {
+        Args {
+            config: Some("../config.yml".into()),
+            overrides: [SECRET]
+                .iter()
+                .chain(overrides)
+                .map(|o| o.to_string())
+                .collect(),
+        }
+    }
+
+    /// the key of an `InvalidConfig` error
+    fn bad_key(result: Result<Config, ServError>) -> String {
+        match result {
+            Err(ServError(RsysError::InvalidConfig { key, .. })) => key,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn layers_override_in_order() {
+        let mut file = Config::defaults().unwrap();
+        merge(
+            &mut file,
+            read_file(Path::new("../config.yml")).await.unwrap(),
+        );
+        set(&mut file, "auth.hs256_secret", "test-secret").unwrap();
+        let file = Config::from_tree(file).unwrap();
+
+        let config = Config::layered_with(&args(&[]), vec![]).await.unwrap();
+        assert_eq!(config, file);
+
+        let vars = env(&[
+            ("DATABASE_URL", "postgres://from-dotenv"),
+            ("RSYS_SERVER__PORT", "2"),
+            ("RSYS_HOLDS__BATCH", "7"),
+            ("OTHER_PORT", "9"),
+        ]);
+        let config = Config::layered_with(&args(&[]), vars.clone())
+            .await
+            .unwrap();
+        assert_eq!(config.db.url, "postgres://from-dotenv");
+        assert_eq!(config.server.port, 2);
+        assert_eq!(config.holds.batch, 7);
+        assert_eq!(config.server.host, file.server.host);
+
+        let mut vars = vars;
+        vars.push((
+            "RSYS_DB__URL".to_string(),
+            "postgres://from-env".to_string(),
+        ));
+        let config = Config::layered_with(&args(&["server.port=3"]), vars)
+            .await
+            .unwrap();
+        assert_eq!(config.db.url, "postgres://from-env");
+        assert_eq!(config.server.port, 3);
+
+        // sections missing from the file are created
+        let config = Config::layered_with(
+            &args(&[
+                "server.tls.cert=server.pem",
+                "server.tls.key=server.key",
+                "server.tls.client_auth_optional=true",
+            ]),
+            vec![],
+        )
+        .await
+        .unwrap();
+        let tls = config.server.tls.unwrap();
+        assert_eq!(tls.cert, "server.pem");
+        assert!(tls.client_auth_optional);
+    }
+
+    #[tokio::test]
+    async fn debug_hides_credentials() {
+        let config = Config::layered_with(
+            &args(&["db.url=postgres://rsys:hunter2@db:5432/rsys"]),
+            env(&[("RSYS_DB__STATEMENT_TIMEOUT_MS", "1500")]),
+        )
+        .await
+        .unwrap();
+        let printed = format!("{:?}", config);
+        assert!(printed.contains("rsys:***@db:5432"), "{}", printed);
+        assert!(!printed.contains("hunter2"), "{}", printed);
+        assert!(!printed.contains(config.auth.hs256_secret.as_ref().unwrap()));
+
+        let options = config.db.options();
+        assert_eq!(options.statement_timeout, Duration::from_millis(1500));
+        assert_eq!(options.max_connections, 10);
+        assert!(!format!("{:?}", options).contains("hunter2"));
+    }
+
+    #[tokio::test]
+    async fn toml_files() {
+        let path = std::env::temp_dir().join(format!("rsys-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[db]\nurl = \"postgres://toml\"\n[auth]\nhs256_secret = \"s\"\n[holds]\nbatch = 5\n",
+        )
+        .unwrap();
+        let args = Args {
+            config: Some(path.clone()),
+            overrides: vec![],
+        };
+        let config = Config::layered_with(&args, vec![]).await.unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(config.db.url, "postgres://toml");
+        assert_eq!(config.holds.batch, 5);
+        // everything else keeps its default
+        assert_eq!(config.server, ServerConfig::default());
+        assert_eq!(config.holds.sweep_interval_secs, 30);
+    }
+
+    #[tokio::test]
+    async fn errors_name_the_key() {
+        let layered = |overrides: &[&str], vars: &[(&str, &str)]| {
+            let args = args(overrides);
+            let vars = env(vars);
+            async move { Config::layered_with(&args, vars).await }
+        };
+        assert_eq!(
+            bad_key(layered(&["server.port=lots"], &[]).await),
+            "server.port"
+        );
+        assert_eq!(
+            bad_key(layered(&["server.port=70000"], &[]).await),
+            "server.port"
+        );
+        assert_eq!(
+            bad_key(layered(&[], &[("RSYS_SERVER__PROT", "1")]).await),
+            "server.prot"
+        );
+        assert_eq!(bad_key(layered(&["db.url="], &[]).await), "db.url");
+        assert_eq!(
+            bad_key(layered(&["auth.issuer="], &[]).await),
+            "auth.issuer"
+        );
+        // no key to check tokens with, so the server refuses to start
+        assert_eq!(bad_key(Config::load("../config.yml").await), "auth");
+        assert_eq!(
+            bad_key(layered(&["holds.batch=0"], &[]).await),
+            "holds.batch"
+        );
+        assert_eq!(bad_key(layered(&["db.url.x=1"], &[]).await), "db.url.x");
+        assert_eq!(bad_key(layered(&["server.port"], &[]).await), "server.port");
+        assert_eq!(
+            bad_key(layered(&["log.format=xml"], &[]).await),
+            "log.format"
+        );
+        assert_eq!(
+            bad_key(layered(&["log.filter=rsys=loud"], &[]).await),
+            "log.filter"
+        );
+
+        let config = layered(&["log.format=json"], &[]).await.unwrap();
+        assert_eq!(config.log.format, LogFormat::Json);
+    }
 }
@@ -1,12 +1,107 @@
-use tonic::Status;
+use prost::Message;
+use prost_types::Any;
+use rsys::error::RsysError;
+use rsys_abi::{google::rpc, ReservationConflictInfo};
+use std::collections::HashMap;
+use tonic::{Code, Status};
+
+/// `ErrorInfo.domain` of every error this service returns
+const ERROR_DOMAIN: &str = "rsys";
 
 #[derive(Debug)]
 pub struct ServError(pub rsys::error::RsysError);
 
+/// the status code a client sees for `err`
+fn code_of(err: &RsysError) -> Code {
+    match err {
+        RsysError::DbError(_) | RsysError::DbxError(_) if err.is_unavailable() => Code::Unavailable,
+        RsysError::Unknown
+        | RsysError::DbError(_)
+        | RsysError::DbxError(_)
+        | RsysError::ConfigError(_)
+        | RsysError::InvalidConfig { .. }
+        | RsysError::ServerError(_) => Code::Internal,
+        RsysError::NoReservation
+        | RsysError::UnknownResource(_)
+        | RsysError::UnknownWaitlistEntry(_) => Code::NotFound,
+        RsysError::AlreadyBooked(_) => Code::AlreadyExists,
+        RsysError::ReservationError(_)
+        | RsysError::NotInSeries(_)
+        | RsysError::ResourceInUse(_)
+        | RsysError::HoldExpired(_)
+        | RsysError::InvalidTransition { .. } => Code::FailedPrecondition,
+        RsysError::InvalidCursor(_)
+        | RsysError::InvalidFieldMask(_)
+        | RsysError::InvalidTimespan
+        | RsysError::InvalidTime(_)
+        | RsysError::InvalidRRule(_)
+        | RsysError::InvalidResource(_)
+        | RsysError::InvalidQuantity(_)
+        | RsysError::InvalidHold(_)
+        | RsysError::InvalidWaitlistEntry(_)
+        | RsysError::InvalidIdempotencyKey(_)
+        | RsysError::InvalidBatch(_)
+        | RsysError::InvalidSearch(_) => Code::InvalidArgument,
+        RsysError::Unauthenticated(_) => Code::Unauthenticated,
+        RsysError::PermissionDenied(_) => Code::PermissionDenied,
+    }
+}
+
+/// `kind()` in UPPER_SNAKE_CASE, e.g. `NO_RESERVATION`
+fn reason_of(err: &RsysError) -> String {
+    let mut reason = String::new();
+    for (i, c) in err.kind().chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            reason.push('_');
+        }
+        reason.push(c.to_ascii_uppercase());
+    }
+    reason
+}
+
+fn error_info(err: &RsysError) -> rpc::ErrorInfo {
+    let metadata = match err {
+        RsysError::InvalidTransition { from, to } => HashMap::from([
+            ("from".to_string(), from.as_str_name().to_string()),
+            ("to".to_string(), to.as_str_name().to_string()),
+        ]),
+        RsysError::InvalidConfig { key, .. } => HashMap::from([("key".to_string(), key.clone())]),
+        _ => HashMap::new(),
+    };
+    rpc::ErrorInfo {
+        reason: reason_of(err),
+        domain: ERROR_DOMAIN.to_string(),
+        metadata,
+    }
+}
+
 impl From<ServError> for tonic::Status {
     fn from(value: ServError) -> Self {
-        let err = value.0.to_string();
-        Status::invalid_argument(err)
+        crate::metrics::record_error(&value.0);
+        let err = value.0;
+        let code = code_of(&err);
+        // server side failures may name hosts, queries or settings, clients
+        // only get their reason and the log keeps the rest
+        let message = match code {
+            Code::Internal => "internal error".to_string(),
+            Code::Unavailable => "service unavailable, retry later".to_string(),
+            _ => err.to_string(),
+        };
+        if matches!(code, Code::Internal | Code::Unavailable) {
+            tracing::error!(error = ?err, kind = err.kind(), "request failed");
+        }
+        let mut details = vec![];
+        if let RsysError::AlreadyBooked(info) = &err {
+            details.push(Any {
+                type_url: ReservationConflictInfo::TYPE_URL.to_string(),
+                value: info.encode_to_vec(),
+            });
+        }
+        details.push(Any {
+            type_url: rpc::ErrorInfo::TYPE_URL.to_string(),
+            value: error_info(&err).encode_to_vec(),
+        });
+        with_details(code, message, details)
     }
 }
 
@@ -16,3 +111,93 @@ impl From<ServError> for anyhow::Error {
         anyhow::anyhow!(err)
     }
 }
+
+/// details are sent as an encoded `google.rpc.Status`, the same layout the
+/// standard gRPC richer error model uses
+fn with_details(code: Code, message: String, details: Vec<Any>) -> Status {
+    let status = rpc::Status {
+        code: code as i32,
+        message: message.clone(),
+        details,
+    };
+    Status::with_details(code, message, status.encode_to_vec().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsys_abi::ReservationStatus;
+
+    /// the `ErrorInfo` packed into `status`
+    fn info(status: &Status) -> rpc::ErrorInfo {
+        let details = rpc::Status::decode(status.details()).unwrap();
+        let any = details
+            .details
+            .iter()
+            .find(|d| d.type_url == rpc::ErrorInfo::TYPE_URL)
+            .unwrap();
+        rpc::ErrorInfo::decode(any.value.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn variants_map_to_codes() {
+        for (err, code, reason) in [
+            (RsysError::NoReservation, Code::NotFound, "NO_RESERVATION"),
+            (
+                RsysError::AlreadyBooked(ReservationConflictInfo::from_ids(["a"])),
+                Code::AlreadyExists,
+                "ALREADY_BOOKED",
+            ),
+            (
+                RsysError::ResourceInUse("r".to_string()),
+                Code::FailedPrecondition,
+                "RESOURCE_IN_USE",
+            ),
+            (
+                RsysError::InvalidTimespan,
+                Code::InvalidArgument,
+                "INVALID_TIMESPAN",
+            ),
+            (
+                RsysError::InvalidConfig {
+                    key: "db.url".to_string(),
+                    reason: "is required".to_string(),
+                },
+                Code::Internal,
+                "INVALID_CONFIG",
+            ),
+            (RsysError::Unknown, Code::Internal, "UNKNOWN"),
+        ] {
+            let status = Status::from(ServError(err));
+            assert_eq!(status.code(), code, "{}", reason);
+            let info = info(&status);
+            assert_eq!(info.reason, reason);
+            assert_eq!(info.domain, ERROR_DOMAIN);
+        }
+
+        let err = RsysError::InvalidTransition {
+            from: ReservationStatus::Cancelled,
+            to: ReservationStatus::Confirmed,
+        };
+        let message = err.to_string();
+        let status = Status::from(ServError(err));
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert_eq!(
+            info(&status).metadata["from"],
+            "RESERVATION_STATUS_CANCELLED"
+        );
+        // client errors keep their message
+        assert_eq!(status.message(), message);
+    }
+
+    #[test]
+    fn internals_stay_in_the_log() {
+        let status = Status::from(ServError(RsysError::ConfigError(
+            "db: cannot reach postgres://rsys:hunter2@db:5432".to_string(),
+        )));
+        assert_eq!(status.code(), Code::Internal);
+        assert_eq!(status.message(), "internal error");
+        assert!(!format!("{:?}", status).contains("hunter2"));
+        assert_eq!(info(&status).reason, "CONFIG_ERROR");
+    }
+}
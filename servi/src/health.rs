@@ -0,0 +1,96 @@
+use crate::{service::RStream, RServic};
+use futures::Stream;
+use rsys::ReservationManager;
+use rsys_abi::grpc::health::v1::{
+    health_check_response::ServingStatus, health_server::Health, HealthCheckRequest,
+    HealthCheckResponse,
+};
+use std::{pin::Pin, time::Duration};
+use tokio::{sync::mpsc, time};
+use tonic::{async_trait, Request, Response, Status};
+
+/// services a health check may name, the empty name stands for the server
+const SERVICES: [&str; 3] = [
+    "",
+    "reservation.ReservationService",
+    "reservation.ResourceService",
+];
+
+/// a ping taking longer than this counts as failed, the pool would otherwise
+/// wait its whole acquire timeout on a database that is gone
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// how often `watch` pings the database
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+type WatchStream = Pin<Box<dyn Stream<Item = Result<HealthCheckResponse, Status>> + Send>>;
+
+/// SERVING while the database answers a ping
+async fn serving(manager: &ReservationManager) -> ServingStatus {
+    match time::timeout(PING_TIMEOUT, manager.ping()).await {
+        Ok(Ok(())) => ServingStatus::Serving,
+        _ => ServingStatus::NotServing,
+    }
+}
+
+fn response(status: ServingStatus) -> HealthCheckResponse {
+    HealthCheckResponse {
+        status: status as i32,
+    }
+}
+
+#[async_trait]
+impl Health for RServic {
+    async fn check(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<HealthCheckResponse>, Status> {
+        let r = request.into_inner();
+        if !SERVICES.contains(&r.service.as_str()) {
+            return Err(Status::not_found(format!("unknown service {}", r.service)));
+        }
+        return Ok(Response::new(response(serving(&self.manager).await)));
+    }
+
+    type WatchStream = WatchStream;
+
+    async fn watch(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        let r = request.into_inner();
+        let manager = self.manager.clone();
+        let draining = self.draining.clone();
+        let (tx, rx) = mpsc::channel(1);
+        tokio::spawn(async move {
+            // unknown services are reported once, the call stays open
+            if !SERVICES.contains(&r.service.as_str()) {
+                if tx
+                    .send(Ok(response(ServingStatus::ServiceUnknown)))
+                    .await
+                    .is_ok()
+                {
+                    tx.closed().await;
+                }
+                return;
+            }
+            let mut ticker = time::interval(WATCH_INTERVAL);
+            let mut last = None;
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = tx.closed() => break,
+                }
+                let status = serving(&manager).await;
+                if last == Some(status) {
+                    continue;
+                }
+                last = Some(status);
+                if tx.send(Ok(response(status))).await.is_err() {
+                    break;
+                }
+            }
+        });
+        return Ok(Response::new(Box::pin(RStream::new(rx, draining))));
+    }
+}
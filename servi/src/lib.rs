@@ -1,23 +1,54 @@
+pub mod auth;
 pub mod config;
 mod error;
+mod health;
+mod metrics;
+mod reflection;
+mod resource;
 mod service;
+pub mod telemetry;
 
 use anyhow::{Ok, Result};
-use config::Config;
-use rsys::ReservationManager;
-use rsys_abi::reservation_service_server::ReservationServiceServer;
-use std::ops::Deref;
+use auth::Authenticator;
+use config::{Config, HoldConfig};
+use metrics::{Event, Metrics, MetricsLayer};
+use reflection::Reflection;
+use rsys::{ReservationManager, Rsvp};
+use rsys_abi::{
+    grpc::{
+        health::v1::health_server::HealthServer,
+        reflection::v1alpha::server_reflection_server::ServerReflectionServer,
+    },
+    reservation_service_server::ReservationServiceServer,
+    resource_service_server::ResourceServiceServer,
+    FILE_DESCRIPTOR_SET,
+};
+use std::{net::SocketAddr, ops::Deref, sync::Arc, time::Duration};
+use telemetry::RequestIdLayer;
+use tokio::{sync::watch, task::JoinHandle};
 
-use tonic::transport::Server;
+use tonic::{
+    service::interceptor::InterceptedService,
+    transport::{server::TcpIncoming, Server},
+};
 
 struct RServic {
     pub manager: ReservationManager,
+    metrics: Metrics,
+    /// flips to true once the server starts shutting down
+    draining: watch::Receiver<bool>,
 }
 
 impl RServic {
-    pub async fn load_from_config(config: &Config) -> anyhow::Result<RServic> {
+    pub async fn load_from_config(
+        config: &Config,
+        metrics: Metrics,
+        draining: watch::Receiver<bool>,
+    ) -> anyhow::Result<RServic> {
         anyhow::Ok(RServic {
-            manager: ReservationManager::new(config.db.url.clone()).await?,
+            manager: ReservationManager::connect(config.db.options()).await?,
+            metrics,
+            draining,
         })
     }
 }
@@ -29,10 +60,141 @@ impl Deref for RServic {
     }
 }
 
+/// cancel expired holds every `sweep_interval_secs` until the service is dropped
+fn spawn_hold_sweeper(svc: &Arc<RServic>, config: &HoldConfig) {
+    let svc = Arc::downgrade(svc);
+    let batch = config.batch;
+    let mut ticker = tokio::time::interval(Duration::from_secs(config.sweep_interval_secs.max(1)));
+    tokio::spawn(async move {
+        loop {
+            ticker.tick().await;
+            let Some(svc) = svc.upgrade() else {
+                break;
+            };
+            // keep going while full batches come back, a backlog should not
+            // wait for the next tick
+            loop {
+                match svc.manager.expire_holds(batch).await {
+                    std::result::Result::Ok(expired) => {
+                        svc.metrics.count(Event::Expired, expired.len());
+                        if (expired.len() as u64) < batch {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        tracing::warn!(%err, "sweeping expired holds failed");
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// a server started by `spawn_server`, dropping it shuts the server down
+/// without waiting for it
+pub struct ServerHandle {
+    shutdown: watch::Sender<bool>,
+    server: JoinHandle<Result<()>>,
+    metrics: Option<(SocketAddr, JoinHandle<Result<()>>)>,
+}
+
+impl ServerHandle {
+    /// stop accepting connections, end open streams with UNAVAILABLE and wait
+    /// for the calls still in flight
+    pub async fn shutdown(self) -> Result<()> {
+        self.shutdown.send_replace(true);
+        if let Some((_, metrics)) = self.metrics {
+            metrics.await??;
+        }
+        self.server.await?
+    }
+
+    /// where `/metrics` is served, `None` if metrics are disabled
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics.as_ref().map(|(addr, _)| *addr)
+    }
+}
+
+/// resolves on SIGINT or SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            std::result::Result::Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
+}
+
+/// serve until SIGINT or SIGTERM, then shut down gracefully
 pub async fn server_start(config: &Config) -> Result<()> {
-    let svc = RServic::load_from_config(config).await?;
-    let svc = ReservationServiceServer::new(svc);
+    let mut handle = spawn_server(config).await?;
+    tokio::select! {
+        served = &mut handle.server => served?,
+        _ = shutdown_signal() => {
+            tracing::info!("shutting down");
+            handle.shutdown().await
+        }
+    }
+}
+
+/// serve `config` in the background. the address is bound before this
+/// returns, so the server is reachable as soon as it does.
+pub async fn spawn_server(config: &Config) -> Result<ServerHandle> {
+    let (shutdown, draining) = watch::channel(false);
+    let auth = Authenticator::new(&config.auth)?;
+    let reflection = Reflection::new(FILE_DESCRIPTOR_SET, draining.clone())?;
+    let metrics = Metrics::new(FILE_DESCRIPTOR_SET)?;
+    let svc = Arc::new(RServic::load_from_config(config, metrics.clone(), draining.clone()).await?);
+    spawn_hold_sweeper(&svc, &config.holds);
     let addr = format!("{}:{}", config.server.host, config.server.port).parse()?;
-    Server::builder().add_service(svc).serve(addr).await?;
-    Ok(())
+    let incoming = TcpIncoming::new(addr, true, None).map_err(|err| anyhow::anyhow!(err))?;
+    let metrics_server = match config.metrics.enabled {
+        true => Some(metrics.serve(&config.metrics, svc.manager.clone(), draining.clone())?),
+        false => None,
+    };
+    if let Some((addr, _)) = &metrics_server {
+        tracing::info!(%addr, "serving metrics");
+    }
+    let mut server = Server::builder()
+        .layer(RequestIdLayer)
+        .layer(MetricsLayer(metrics));
+    if let Some(tls) = &config.server.tls {
+        server = server.tls_config(tls.load().await?)?;
+    }
+    // health and reflection stay reachable without a token, probes and
+    // grpcurl do not carry one
+    let router = server
+        .add_service(HealthServer::from_arc(svc.clone()))
+        .add_service(ServerReflectionServer::new(reflection))
+        .add_service(InterceptedService::new(
+            ReservationServiceServer::from_arc(svc.clone()),
+            auth.clone(),
+        ))
+        .add_service(InterceptedService::new(
+            ResourceServiceServer::from_arc(svc),
+            auth,
+        ));
+    tracing::info!(%addr, tls = config.server.tls.is_some(), "serving");
+    let server = tokio::spawn(async move {
+        router
+            .serve_with_incoming_shutdown(incoming, service::drained(draining))
+            .await?;
+        Ok(())
+    });
+    Ok(ServerHandle {
+        shutdown,
+        server,
+        metrics: metrics_server,
+    })
 }
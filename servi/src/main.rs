@@ -1,15 +1,14 @@
-use std::env;
-
 use anyhow::Result;
-use rsys_servi::config::Config;
-use rsys_servi::server_start;
+use clap::Parser;
+use rsys_servi::config::{Args, Config};
+use rsys_servi::{server_start, telemetry};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let path = env::current_dir();
-    println!("{:?}", path.unwrap().as_mut_os_string());
-    let config = Config::load("./config.yml").await?;
-    println!("{:?}", config);
+    let args = Args::parse();
+    let config = Config::layered(&args).await?;
+    let _telemetry = telemetry::init(&config.log)?;
+    tracing::info!(?config, "loaded config");
     server_start(&config).await
 }
 
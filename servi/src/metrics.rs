@@ -0,0 +1,361 @@
+use crate::{config::MetricsConfig, error::ServError, service::drained};
+use futures::future::BoxFuture;
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, StatusCode,
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use prost::Message;
+use prost_types::FileDescriptorSet;
+use rsys::{error::RsysError, ReservationManager};
+use std::{
+    cell::Cell,
+    collections::HashSet,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+use tokio::{sync::watch, task::JoinHandle};
+use tonic::{body::BoxBody, Code};
+use tower::{Layer, Service};
+
+tokio::task_local! {
+    /// variant of the `RsysError` the current call failed with, filled in
+    /// when it is turned into a `Status`
+    static RPC_ERROR: Cell<Option<&'static str>>;
+}
+
+/// note the `RsysError` variant a call is about to fail with, a no-op outside
+/// of a call counted by `MetricsLayer`
+pub(crate) fn record_error(err: &RsysError) {
+    let _ = RPC_ERROR.try_with(|e| e.set(Some(err.kind())));
+}
+
+/// a reservation changed state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Event {
+    Created,
+    Confirmed,
+    Cancelled,
+    Expired,
+}
+
+impl Event {
+    fn label(self) -> &'static str {
+        match self {
+            Event::Created => "created",
+            Event::Confirmed => "confirmed",
+            Event::Cancelled => "cancelled",
+            Event::Expired => "expired",
+        }
+    }
+}
+
+/// everything `/metrics` reports
+#[derive(Clone)]
+pub(crate) struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    duration: HistogramVec,
+    errors: IntCounterVec,
+    streams: IntGaugeVec,
+    pool: IntGaugeVec,
+    reservations: IntCounterVec,
+    /// `/package.Service/method` of every served RPC, anything else is
+    /// counted as `unknown` to keep the label set bounded
+    methods: Arc<HashSet<String>>,
+}
+
+fn registered<T: prometheus::core::Collector + Clone + 'static>(
+    registry: &Registry,
+    collector: Result<T, prometheus::Error>,
+) -> Result<T, ServError> {
+    let invalid =
+        |err: prometheus::Error| ServError(RsysError::ConfigError(format!("metrics: {}", err)));
+    let collector = collector.map_err(invalid)?;
+    registry
+        .register(Box::new(collector.clone()))
+        .map_err(invalid)?;
+    Ok(collector)
+}
+
+impl Metrics {
+    /// `descriptor_set` is an encoded `FileDescriptorSet` naming the RPCs
+    pub(crate) fn new(descriptor_set: &[u8]) -> Result<Self, ServError> {
+        let set = FileDescriptorSet::decode(descriptor_set).map_err(|_| {
+            ServError(RsysError::ConfigError(
+                "metrics: invalid descriptor set".to_string(),
+            ))
+        })?;
+        let mut methods = HashSet::new();
+        for file in &set.file {
+            for service in &file.service {
+                for method in &service.method {
+                    methods.insert(format!(
+                        "/{}.{}/{}",
+                        file.package(),
+                        service.name(),
+                        method.name()
+                    ));
+                }
+            }
+        }
+
+        let registry = Registry::new();
+        let requests = registered(
+            &registry,
+            IntCounterVec::new(
+                Opts::new("rsys_rpc_requests_total", "RPCs received"),
+                &["method"],
+            ),
+        )?;
+        let duration = registered(
+            &registry,
+            HistogramVec::new(
+                HistogramOpts::new(
+                    "rsys_rpc_duration_seconds",
+                    "time until an RPC's response headers, streams count until they open",
+                ),
+                &["method"],
+            ),
+        )?;
+        let errors = registered(
+            &registry,
+            IntCounterVec::new(
+                Opts::new(
+                    "rsys_rpc_errors_total",
+                    "failed RPCs by RsysError variant, or by status code for other errors",
+                ),
+                &["method", "error"],
+            ),
+        )?;
+        let streams = registered(
+            &registry,
+            IntGaugeVec::new(
+                Opts::new("rsys_active_streams", "open query and listen streams"),
+                &["method"],
+            ),
+        )?;
+        let pool = registered(
+            &registry,
+            IntGaugeVec::new(
+                Opts::new(
+                    "rsys_db_pool_connections",
+                    "database connections by state, max is the pool's limit",
+                ),
+                &["state"],
+            ),
+        )?;
+        let reservations = registered(
+            &registry,
+            IntCounterVec::new(
+                Opts::new(
+                    "rsys_reservations_total",
+                    "reservations created, confirmed, cancelled or expired",
+                ),
+                &["event"],
+            ),
+        )?;
+        Ok(Metrics {
+            registry,
+            requests,
+            duration,
+            errors,
+            streams,
+            pool,
+            reservations,
+            methods: Arc::new(methods),
+        })
+    }
+
+    pub(crate) fn count(&self, event: Event, n: usize) {
+        self.reservations
+            .with_label_values(&[event.label()])
+            .inc_by(n as u64);
+    }
+
+    /// gauge of the open streams of `method`
+    pub(crate) fn stream(&self, method: &str) -> IntGauge {
+        self.streams.with_label_values(&[method])
+    }
+
+    fn method<'a>(&self, path: &'a str) -> &'a str {
+        match self.methods.contains(path) {
+            true => path,
+            false => "unknown",
+        }
+    }
+
+    /// the text exposition of every metric, pool gauges as of now
+    fn render(&self, manager: &ReservationManager) -> Vec<u8> {
+        let status = manager.pool_status();
+        let idle = status.idle as i64;
+        self.pool.with_label_values(&["idle"]).set(idle);
+        self.pool
+            .with_label_values(&["in_use"])
+            .set(status.size as i64 - idle);
+        self.pool.with_label_values(&["max"]).set(status.max as i64);
+
+        let mut buffer = vec![];
+        // writing into a Vec only fails on malformed metrics
+        let _ = TextEncoder::new().encode(&self.registry.gather(), &mut buffer);
+        buffer
+    }
+
+    /// serve `/metrics` on `config` until `draining` flips, returns the bound
+    /// address
+    pub(crate) fn serve(
+        &self,
+        config: &MetricsConfig,
+        manager: ReservationManager,
+        draining: watch::Receiver<bool>,
+    ) -> anyhow::Result<(SocketAddr, JoinHandle<anyhow::Result<()>>)> {
+        let addr: SocketAddr = format!("{}:{}", config.host, config.port).parse()?;
+        let metrics = self.clone();
+        let make = make_service_fn(move |_| {
+            let metrics = metrics.clone();
+            let manager = manager.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                    let response = match (request.method(), request.uri().path()) {
+                        (&Method::GET, "/metrics") => hyper::Response::builder()
+                            .header(CONTENT_TYPE, TextEncoder::new().format_type())
+                            .body(Body::from(metrics.render(&manager))),
+                        _ => hyper::Response::builder()
+                            .status(StatusCode::NOT_FOUND)
+                            .body(Body::empty()),
+                    };
+                    async move { response }
+                }))
+            }
+        });
+        let server = hyper::Server::try_bind(&addr)?.serve(make);
+        let addr = server.local_addr();
+        let server = server.with_graceful_shutdown(drained(draining));
+        let server = tokio::spawn(async move {
+            server.await?;
+            Ok(())
+        });
+        Ok((addr, server))
+    }
+}
+
+/// counts every RPC passing through the server
+#[derive(Clone)]
+pub(crate) struct MetricsLayer(pub(crate) Metrics);
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService {
+            inner,
+            metrics: self.0.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct MetricsService<S> {
+    inner: S,
+    metrics: Metrics,
+}
+
+impl<S> Service<hyper::Request<Body>> for MetricsService<S>
+where
+    S: Service<hyper::Request<Body>, Response = hyper::Response<BoxBody>> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: hyper::Request<Body>) -> Self::Future {
+        let method = self.metrics.method(request.uri().path()).to_string();
+        let metrics = self.metrics.clone();
+        let started = Instant::now();
+        // interceptors reject calls in `call` already, the handler only
+        // fails once the future runs
+        let (rejected, call) = RPC_ERROR.sync_scope(Cell::new(None), || {
+            let call = self.inner.call(request);
+            (RPC_ERROR.with(Cell::get), call)
+        });
+        Box::pin(RPC_ERROR.scope(Cell::new(rejected), async move {
+            let response = call.await;
+            metrics.requests.with_label_values(&[&method]).inc();
+            metrics
+                .duration
+                .with_label_values(&[&method])
+                .observe(started.elapsed().as_secs_f64());
+            // failed calls carry their status in the headers, successful ones
+            // only in the trailers
+            let code = response.as_ref().ok().and_then(|r| {
+                r.headers()
+                    .get("grpc-status")
+                    .map(|s| Code::from_bytes(s.as_bytes()))
+            });
+            if let Some(code) = code.filter(|c| *c != Code::Ok) {
+                let error = match RPC_ERROR.with(Cell::get) {
+                    Some(kind) => kind.to_string(),
+                    None => format!("{:?}", code),
+                };
+                metrics.errors.with_label_values(&[&method, &error]).inc();
+            }
+            response
+        }))
+    }
+}
+
+/// counts one open stream until dropped
+pub(crate) struct ActiveStream(IntGauge);
+
+impl ActiveStream {
+    pub(crate) fn new(gauge: IntGauge) -> Self {
+        gauge.inc();
+        ActiveStream(gauge)
+    }
+}
+
+impl Drop for ActiveStream {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn methods_are_bounded() {
+        let metrics = Metrics::new(rsys_abi::FILE_DESCRIPTOR_SET).unwrap();
+        assert_eq!(
+            metrics.method("/reservation.ReservationService/reserve"),
+            "/reservation.ReservationService/reserve"
+        );
+        assert_eq!(
+            metrics.method("/grpc.health.v1.Health/Check"),
+            "/grpc.health.v1.Health/Check"
+        );
+        assert_eq!(
+            metrics.method("/reservation.ReservationService/nope"),
+            "unknown"
+        );
+
+        let gauge = metrics.stream("listen");
+        let stream = ActiveStream::new(gauge.clone());
+        assert_eq!(gauge.get(), 1);
+        drop(stream);
+        assert_eq!(gauge.get(), 0);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::{error::ServError, service::RStream};
+use futures::Stream;
+use prost::Message;
+use prost_types::{DescriptorProto, FileDescriptorProto, FileDescriptorSet};
+use rsys::error::RsysError;
+use rsys_abi::grpc::reflection::v1alpha::{
+    server_reflection_request::MessageRequest, server_reflection_response::MessageResponse,
+    server_reflection_server::ServerReflection, ErrorResponse, ExtensionNumberResponse,
+    FileDescriptorResponse, ListServiceResponse, ServerReflectionRequest, ServerReflectionResponse,
+    ServiceResponse,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    pin::Pin,
+    sync::Arc,
+};
+use tokio::sync::{mpsc, watch};
+use tonic::{async_trait, Code, Request, Response, Status, Streaming};
+
+type InfoStream = Pin<Box<dyn Stream<Item = Result<ServerReflectionResponse, Status>> + Send>>;
+
+/// answers `grpc.reflection.v1alpha` requests from a compiled descriptor set
+#[derive(Clone)]
+pub(crate) struct Reflection {
+    index: Arc<Index>,
+    draining: watch::Receiver<bool>,
+}
+
+#[derive(Default)]
+struct Index {
+    files: HashMap<String, FileDescriptorProto>,
+    /// fully qualified name of every message, enum, service and method, to
+    /// the file declaring it
+    symbols: HashMap<String, String>,
+    services: Vec<String>,
+}
+
+fn qualified(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        return name.to_string();
+    }
+    format!("{}.{}", scope, name)
+}
+
+impl Index {
+    fn add_message(&mut self, file: &str, scope: &str, message: &DescriptorProto) {
+        let name = qualified(scope, message.name());
+        for nested in &message.nested_type {
+            self.add_message(file, &name, nested);
+        }
+        for e in &message.enum_type {
+            self.symbols
+                .insert(qualified(&name, e.name()), file.to_string());
+        }
+        self.symbols.insert(name, file.to_string());
+    }
+
+    fn add_file(&mut self, file: FileDescriptorProto) {
+        let name = file.name().to_string();
+        let package = file.package();
+        for message in &file.message_type {
+            self.add_message(&name, package, message);
+        }
+        for e in &file.enum_type {
+            self.symbols
+                .insert(qualified(package, e.name()), name.clone());
+        }
+        for service in &file.service {
+            let service_name = qualified(package, service.name());
+            for method in &service.method {
+                self.symbols
+                    .insert(qualified(&service_name, method.name()), name.clone());
+            }
+            self.symbols.insert(service_name.clone(), name.clone());
+            self.services.push(service_name);
+        }
+        self.files.insert(name, file);
+    }
+
+    /// `name` and everything it imports, `name` first
+    fn file_with_imports(&self, name: &str) -> Option<Vec<Vec<u8>>> {
+        let mut seen = HashSet::new();
+        let mut pending = vec![name];
+        let mut encoded = vec![];
+        while let Some(name) = pending.pop() {
+            if !seen.insert(name) {
+                continue;
+            }
+            let file = self.files.get(name)?;
+            encoded.push(file.encode_to_vec());
+            pending.extend(file.dependency.iter().map(String::as_str));
+        }
+        Some(encoded)
+    }
+
+    fn files_response(&self, name: Option<&String>, what: &str) -> MessageResponse {
+        match name.and_then(|name| self.file_with_imports(name)) {
+            Some(files) => MessageResponse::FileDescriptorResponse(FileDescriptorResponse {
+                file_descriptor_proto: files,
+            }),
+            None => not_found(what),
+        }
+    }
+
+    fn answer(&self, request: ServerReflectionRequest) -> ServerReflectionResponse {
+        let response = match &request.message_request {
+            Some(MessageRequest::FileByFilename(name)) => {
+                self.files_response(Some(name), &format!("file {}", name))
+            }
+            Some(MessageRequest::FileContainingSymbol(symbol)) => {
+                self.files_response(self.symbols.get(symbol), &format!("symbol {}", symbol))
+            }
+            // none of our protos declare extensions
+            Some(MessageRequest::AllExtensionNumbersOfType(ty))
+                if self.symbols.contains_key(ty) =>
+            {
+                MessageResponse::AllExtensionNumbersResponse(ExtensionNumberResponse {
+                    base_type_name: ty.clone(),
+                    extension_number: vec![],
+                })
+            }
+            Some(MessageRequest::AllExtensionNumbersOfType(ty)) => {
+                not_found(&format!("type {}", ty))
+            }
+            Some(MessageRequest::FileContainingExtension(ext)) => not_found(&format!(
+                "extension {} of {}",
+                ext.extension_number, ext.containing_type
+            )),
+            Some(MessageRequest::ListServices(_)) => {
+                MessageResponse::ListServicesResponse(ListServiceResponse {
+                    service: self
+                        .services
+                        .iter()
+                        .map(|name| ServiceResponse { name: name.clone() })
+                        .collect(),
+                })
+            }
+            None => MessageResponse::ErrorResponse(ErrorResponse {
+                error_code: Code::InvalidArgument as i32,
+                error_message: "empty request".to_string(),
+            }),
+        };
+        ServerReflectionResponse {
+            valid_host: request.host.clone(),
+            original_request: Some(request),
+            message_response: Some(response),
+        }
+    }
+}
+
+fn not_found(what: &str) -> MessageResponse {
+    MessageResponse::ErrorResponse(ErrorResponse {
+        error_code: Code::NotFound as i32,
+        error_message: format!("unknown {}", what),
+    })
+}
+
+impl Reflection {
+    /// `descriptor_set` is an encoded `FileDescriptorSet` holding the files
+    /// of the served services and all their imports
+    pub(crate) fn new(
+        descriptor_set: &[u8],
+        draining: watch::Receiver<bool>,
+    ) -> Result<Self, ServError> {
+        let set = FileDescriptorSet::decode(descriptor_set).map_err(|_| {
+            ServError(RsysError::ConfigError(
+                "reflection: invalid descriptor set".to_string(),
+            ))
+        })?;
+        let mut index = Index::default();
+        for file in set.file {
+            index.add_file(file);
+        }
+        Ok(Reflection {
+            index: Arc::new(index),
+            draining,
+        })
+    }
+}
+
+#[async_trait]
+impl ServerReflection for Reflection {
+    type ServerReflectionInfoStream = InfoStream;
+
+    async fn server_reflection_info(
+        &self,
+        request: Request<Streaming<ServerReflectionRequest>>,
+    ) -> Result<Response<Self::ServerReflectionInfoStream>, Status> {
+        let mut requests = request.into_inner();
+        let index = self.index.clone();
+        let (tx, rx) = mpsc::channel(16);
+        tokio::spawn(async move {
+            while let Ok(Some(request)) = requests.message().await {
+                if tx.send(Ok(index.answer(request))).await.is_err() {
+                    break;
+                }
+            }
+        });
+        return Ok(Response::new(Box::pin(RStream::new(
+            rx,
+            self.draining.clone(),
+        ))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ask(reflection: &Reflection, request: MessageRequest) -> MessageResponse {
+        let response = reflection.index.answer(ServerReflectionRequest {
+            host: String::new(),
+            message_request: Some(request),
+        });
+        response.message_response.unwrap()
+    }
+
+    fn file_names(response: MessageResponse) -> Vec<String> {
+        let MessageResponse::FileDescriptorResponse(files) = response else {
+            panic!("{:?}", response);
+        };
+        files
+            .file_descriptor_proto
+            .iter()
+            .map(|f| {
+                FileDescriptorProto::decode(f.as_slice())
+                    .unwrap()
+                    .name()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn describes_compiled_protos() {
+        let (_shutdown, draining) = watch::channel(false);
+        let reflection = Reflection::new(rsys_abi::FILE_DESCRIPTOR_SET, draining).unwrap();
+
+        let MessageResponse::ListServicesResponse(list) =
+            ask(&reflection, MessageRequest::ListServices(String::new()))
+        else {
+            panic!("no service list");
+        };
+        let services: Vec<_> = list.service.into_iter().map(|s| s.name).collect();
+        assert!(services.contains(&"reservation.ReservationService".to_string()));
+        assert!(services.contains(&"grpc.health.v1.Health".to_string()));
+
+        for symbol in [
+            "reservation.ReservationService",
+            "reservation.ReservationService.reserve",
+            "reservation.Reservation",
+            "reservation.ReservationStatus",
+        ] {
+            let files = file_names(ask(
+                &reflection,
+                MessageRequest::FileContainingSymbol(symbol.to_string()),
+            ));
+            assert_eq!(files[0], "reservation.proto");
+            assert!(files.contains(&"google/protobuf/timestamp.proto".to_string()));
+        }
+        let files = file_names(ask(
+            &reflection,
+            MessageRequest::FileByFilename("grpc/health/v1/health.proto".to_string()),
+        ));
+        assert_eq!(files, vec!["grpc/health/v1/health.proto"]);
+
+        assert!(matches!(
+            ask(
+                &reflection,
+                MessageRequest::FileContainingSymbol("reservation.Nope".to_string())
+            ),
+            MessageResponse::ErrorResponse(ErrorResponse { error_code, .. }) if error_code == Code::NotFound as i32
+        ));
+    }
+}
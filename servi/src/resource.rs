@@ -0,0 +1,86 @@
+use crate::{
+    auth::{caller_of, require_admin},
+    error::ServError,
+    telemetry::request_id,
+    RServic,
+};
+use rsys::ResourceRegistry;
+use rsys_abi::*;
+use tonic::{async_trait, Request, Response, Status};
+use tracing::instrument;
+
+#[async_trait]
+impl rsys_abi::resource_service_server::ResourceService for RServic {
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
+    async fn create(
+        &self,
+        request: Request<CreateResourceRequest>,
+    ) -> Result<Response<Resource>, Status> {
+        require_admin(&caller_of(&request)?)?;
+        let r = request.into_inner();
+        if let Some(resource) = r.resource {
+            let r = self.manager.create_resource(resource).await;
+            if r.is_err() {
+                return Err(ServError(r.err().unwrap()).into());
+            }
+            return Ok(Response::new(r.unwrap()));
+        }
+        return Err(Status::invalid_argument("no resource"));
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
+    async fn get(
+        &self,
+        request: Request<GetResourceRequest>,
+    ) -> Result<Response<Resource>, Status> {
+        let r = request.into_inner();
+        let r = self.manager.get_resource(&r.id).await;
+        if r.is_err() {
+            return Err(ServError(r.err().unwrap()).into());
+        }
+        return Ok(Response::new(r.unwrap()));
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
+    async fn update(
+        &self,
+        request: Request<UpdateResourceRequest>,
+    ) -> Result<Response<Resource>, Status> {
+        require_admin(&caller_of(&request)?)?;
+        let r = request.into_inner();
+        let r = self.manager.update_resource(r).await;
+        if r.is_err() {
+            return Err(ServError(r.err().unwrap()).into());
+        }
+        return Ok(Response::new(r.unwrap()));
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
+    async fn delete(
+        &self,
+        request: Request<DeleteResourceRequest>,
+    ) -> Result<Response<Resource>, Status> {
+        require_admin(&caller_of(&request)?)?;
+        let r = request.into_inner();
+        let r = self.manager.delete_resource(&r.id).await;
+        if r.is_err() {
+            return Err(ServError(r.err().unwrap()).into());
+        }
+        return Ok(Response::new(r.unwrap()));
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
+    async fn list(
+        &self,
+        request: Request<ListResourcesRequest>,
+    ) -> Result<Response<ListResourcesResponse>, Status> {
+        let r = request.into_inner();
+        let r = self.manager.list_resources(r.include_inactive).await;
+        if r.is_err() {
+            return Err(ServError(r.err().unwrap()).into());
+        }
+        return Ok(Response::new(ListResourcesResponse {
+            resources: r.unwrap(),
+        }));
+    }
+}
@@ -1,21 +1,47 @@
-use crate::{error::ServError, RServic};
+use crate::{
+    auth::{caller_of, claim},
+    error::ServError,
+    metrics::{ActiveStream, Event},
+    telemetry::request_id,
+    RServic,
+};
 use futures::Stream;
-use rsys::{error::RsysError, Rsvp};
+use prometheus::IntGauge;
+use rsys::{error::RsysError, Rsvp, Waitlist};
 use rsys_abi::*;
-use std::{pin::Pin, task::Poll};
-use tokio::sync::mpsc::Receiver;
+use std::{future::Future, pin::Pin, task::Poll};
+use tokio::sync::{mpsc::Receiver, watch};
 use tonic::{async_trait, Request, Response, Status};
+use tracing::instrument;
 
 pub struct RStream<T> {
     inner: Receiver<Result<T, RsysError>>,
+    /// resolves once the server starts shutting down, `None` after that
+    draining: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    active: Option<ActiveStream>,
 }
 
 impl<T> RStream<T> {
-    pub fn new(inner: Receiver<Result<T, RsysError>>) -> Self {
-        RStream { inner }
+    pub fn new(inner: Receiver<Result<T, RsysError>>, draining: watch::Receiver<bool>) -> Self {
+        RStream {
+            inner,
+            draining: Some(Box::pin(drained(draining))),
+            active: None,
+        }
+    }
+
+    /// count this stream in `gauge` while it is open
+    pub(crate) fn tracked(mut self, gauge: IntGauge) -> Self {
+        self.active = Some(ActiveStream::new(gauge));
+        self
     }
 }
 
+/// wait for `draining` to flip or its sender to go away
+pub(crate) async fn drained(mut draining: watch::Receiver<bool>) {
+    let _ = draining.wait_for(|d| *d).await;
+}
+
 impl<T> Stream for RStream<T> {
     type Item = Result<T, Status>;
 
@@ -23,73 +49,204 @@ impl<T> Stream for RStream<T> {
         mut self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
+        // a graceful shutdown waits for every open call, so long lived
+        // streams are ended here instead of keeping the server up
+        let Some(draining) = self.draining.as_mut() else {
+            return Poll::Ready(None);
+        };
+        if draining.as_mut().poll(cx).is_ready() {
+            self.draining = None;
+            self.active = None;
+            self.inner.close();
+            return Poll::Ready(Some(Err(Status::unavailable("server is shutting down"))));
+        }
         match self.inner.poll_recv(cx) {
             Poll::Ready(Some(Ok(i))) => Poll::Ready(Some(Ok(i))),
             Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(ServError(e).into()))),
-            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Ready(None) => {
+                self.active = None;
+                Poll::Ready(None)
+            }
             Poll::Pending => Poll::Pending,
         }
     }
 }
 
+/// request header read when `ReserveRequest.idempotency_key` is empty
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
 type ReservationStream = Pin<Box<dyn Stream<Item = Result<Reservation, Status>> + Send>>;
+type ListenStream = Pin<Box<dyn Stream<Item = Result<ListenResponse, Status>> + Send>>;
 
 #[async_trait]
 impl rsys_abi::reservation_service_server::ReservationService for RServic {
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
     async fn reserve(
         &self,
         request: Request<ReserveRequest>,
     ) -> Result<Response<Reservation>, Status> {
-        let r = request.into_inner();
-        if let Some(reservation) = r.reservation {
-            let r = self.manager.create(reservation).await;
-            if r.is_err() {
-                return Err(ServError(r.err().unwrap()).into());
-            }
-            return Ok(Response::new(r.unwrap()));
+        let caller = caller_of(&request)?;
+        let key = request
+            .metadata()
+            .get(IDEMPOTENCY_KEY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let mut r = request.into_inner();
+        let Some(reservation) = r.reservation.as_mut() else {
+            return Err(Status::invalid_argument("no reservation"));
+        };
+        claim(&caller, &mut reservation.uid)?;
+        if r.idempotency_key.is_empty() {
+            r.idempotency_key = key.unwrap_or_default();
+        }
+        let r = self.manager.reserve(r, &caller).await;
+        if r.is_err() {
+            return Err(ServError(r.err().unwrap()).into());
+        }
+        let (reservation, replayed) = r.unwrap();
+        // a replayed key created nothing new
+        if !replayed {
+            self.metrics.count(Event::Created, 1);
         }
-        return Err(Status::invalid_argument("no reservation"));
+        return Ok(Response::new(reservation));
     }
 
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
     async fn confirm(
         &self,
         request: Request<ConfirmRequest>,
     ) -> Result<Response<Reservation>, Status> {
+        let caller = caller_of(&request)?;
         let r = request.into_inner();
-        let r = self.manager.change_status(r).await;
+        let r = self.manager.change_status(r, &caller).await;
         if r.is_err() {
             return Err(ServError(r.err().unwrap()).into());
         }
+        self.metrics.count(Event::Confirmed, 1);
         return Ok(Response::new(r.unwrap()));
     }
 
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
     async fn update(
         &self,
         request: Request<UpdateRequest>,
     ) -> Result<Response<Reservation>, Status> {
+        let caller = caller_of(&request)?;
         let r = request.into_inner();
-        let r = self.manager.update_note(r).await;
+        let r = self.manager.update(r, &caller).await;
         if r.is_err() {
             return Err(ServError(r.err().unwrap()).into());
         }
         return Ok(Response::new(r.unwrap()));
     }
 
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
     async fn cancel(
         &self,
         request: Request<CancelRequest>,
-    ) -> Result<Response<ActionResponse>, Status> {
+    ) -> Result<Response<Reservation>, Status> {
+        let caller = caller_of(&request)?;
+        let r = request.into_inner();
+        let r = self.manager.cancel(r, &caller).await;
+        if r.is_err() {
+            return Err(ServError(r.err().unwrap()).into());
+        }
+        self.metrics.count(Event::Cancelled, 1);
+        return Ok(Response::new(r.unwrap()));
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
+    async fn reserve_batch(
+        &self,
+        request: Request<ReserveBatchRequest>,
+    ) -> Result<Response<ReserveBatchResponse>, Status> {
+        let caller = caller_of(&request)?;
+        let mut r = request.into_inner();
+        for reservation in r.reservations.iter_mut() {
+            claim(&caller, &mut reservation.uid)?;
+        }
+        let r = self.manager.reserve_batch(r, &caller).await;
+        if r.is_err() {
+            return Err(ServError(r.err().unwrap()).into());
+        }
+        let r = r.unwrap();
+        self.metrics.count(Event::Created, r.reservations.len());
+        return Ok(Response::new(r));
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
+    async fn reserve_series(
+        &self,
+        request: Request<ReserveSeriesRequest>,
+    ) -> Result<Response<ReserveSeriesResponse>, Status> {
+        let caller = caller_of(&request)?;
+        let mut r = request.into_inner();
+        let Some(reservation) = r.reservation.as_mut() else {
+            return Err(Status::invalid_argument("no reservation"));
+        };
+        claim(&caller, &mut reservation.uid)?;
+        let r = self.manager.reserve_series(r, &caller).await;
+        if r.is_err() {
+            return Err(ServError(r.err().unwrap()).into());
+        }
+        let r = r.unwrap();
+        self.metrics.count(Event::Created, r.reservations.len());
+        return Ok(Response::new(r));
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
+    async fn update_series(
+        &self,
+        request: Request<UpdateSeriesRequest>,
+    ) -> Result<Response<SeriesResponse>, Status> {
+        let caller = caller_of(&request)?;
+        let r = request.into_inner();
+        let r = self.manager.update_series(r, &caller).await;
+        if r.is_err() {
+            return Err(ServError(r.err().unwrap()).into());
+        }
+        return Ok(Response::new(SeriesResponse {
+            reservations: r.unwrap(),
+        }));
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
+    async fn cancel_series(
+        &self,
+        request: Request<CancelSeriesRequest>,
+    ) -> Result<Response<SeriesResponse>, Status> {
+        let caller = caller_of(&request)?;
         let r = request.into_inner();
-        let r = self.manager.delete(r).await;
+        let r = self.manager.cancel_series(r, &caller).await;
         if r.is_err() {
             return Err(ServError(r.err().unwrap()).into());
         }
-        return Ok(Response::new(ActionResponse { done: true }));
+        let r = r.unwrap();
+        self.metrics.count(Event::Cancelled, r.len());
+        return Ok(Response::new(SeriesResponse { reservations: r }));
     }
 
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
+    async fn purge(
+        &self,
+        request: Request<PurgeRequest>,
+    ) -> Result<Response<PurgeResponse>, Status> {
+        let caller = caller_of(&request)?;
+        let r = request.into_inner();
+        let r = self.manager.purge(r, &caller).await;
+        if r.is_err() {
+            return Err(ServError(r.err().unwrap()).into());
+        }
+        return Ok(Response::new(PurgeResponse {
+            purged: r.unwrap() as i64,
+        }));
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
     async fn get(&self, request: Request<GetRequest>) -> Result<Response<Reservation>, Status> {
+        let caller = caller_of(&request)?;
         let r = request.into_inner();
-        let r = self.manager.get(r).await;
+        let r = self.manager.get(r, &caller).await;
         if r.is_err() {
             return Err(ServError(r.err().unwrap()).into());
         }
@@ -98,43 +255,128 @@ impl rsys_abi::reservation_service_server::ReservationService for RServic {
 
     type queryStream = ReservationStream;
 
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
     async fn query(
         &self,
         request: Request<QueryRequest>,
     ) -> Result<Response<Self::queryStream>, Status> {
+        let caller = caller_of(&request)?;
+        let r = request.into_inner();
+        let r = self.manager.query(r, &caller).await;
+        return Ok(Response::new(Box::pin(
+            RStream::new(r, self.draining.clone()).tracked(self.metrics.stream("query")),
+        )));
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
+    async fn filter(
+        &self,
+        request: Request<FilterRequest>,
+    ) -> Result<Response<FilterResponse>, Status> {
+        let caller = caller_of(&request)?;
+        let r = request.into_inner();
+        let r = self.manager.filter(r, &caller).await;
+        if r.is_err() {
+            return Err(ServError(r.err().unwrap()).into());
+        }
+        return Ok(Response::new(r.unwrap()));
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
+    async fn availability(
+        &self,
+        request: Request<AvailabilityRequest>,
+    ) -> Result<Response<AvailabilityResponse>, Status> {
         let r = request.into_inner();
-        let r = self.manager.query(r).await;
-        return Ok(Response::new(Box::pin(RStream::new(r))));
+        let r = self.manager.availability(r).await;
+        if r.is_err() {
+            return Err(ServError(r.err().unwrap()).into());
+        }
+        return Ok(Response::new(r.unwrap()));
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
+    async fn join_waitlist(
+        &self,
+        request: Request<JoinWaitlistRequest>,
+    ) -> Result<Response<WaitlistEntry>, Status> {
+        let caller = caller_of(&request)?;
+        let r = request.into_inner();
+        if let Some(mut entry) = r.entry {
+            claim(&caller, &mut entry.uid)?;
+            let r = self.manager.join_waitlist(entry, &caller).await;
+            if r.is_err() {
+                return Err(ServError(r.err().unwrap()).into());
+            }
+            return Ok(Response::new(r.unwrap()));
+        }
+        return Err(Status::invalid_argument("no waitlist entry"));
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
+    async fn leave_waitlist(
+        &self,
+        request: Request<LeaveWaitlistRequest>,
+    ) -> Result<Response<WaitlistEntry>, Status> {
+        let caller = caller_of(&request)?;
+        let r = request.into_inner();
+        let r = self.manager.leave_waitlist(&r.id, &caller).await;
+        if r.is_err() {
+            return Err(ServError(r.err().unwrap()).into());
+        }
+        return Ok(Response::new(r.unwrap()));
     }
 
-    type listenStream = ReservationStream;
+    type listenStream = ListenStream;
 
+    #[instrument(skip_all, fields(request_id = %request_id(&request)))]
     async fn listen(
         &self,
         request: Request<ListenRequest>,
     ) -> Result<Response<Self::listenStream>, Status> {
+        let caller = caller_of(&request)?;
         let r = request.into_inner();
-        let r = self.manager.listen(r).await;
+        let r = self.manager.listen(r, &caller).await;
         if r.is_err() {
             return Err(ServError(r.err().unwrap()).into());
         }
-        todo!()
+        return Ok(Response::new(Box::pin(
+            RStream::new(r.unwrap(), self.draining.clone()).tracked(self.metrics.stream("listen")),
+        )));
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::Config;
+    use crate::config::{Args, Config};
+    use rsys::ResourceRegistry;
     use rsys_abi::reservation_service_server::ReservationService;
 
     #[tokio::test]
     async fn test() {
-        let config = Config::load("../config.yml").await.unwrap();
-        let svc = RServic::load_from_config(&config).await.unwrap();
-        let req = tonic::Request::new(ReserveRequest {
-            reservation: Some(rsys::generate_random_reservation()),
+        let args = Args {
+            config: Some("../config.yml".into()),
+            overrides: vec!["auth.hs256_secret=test-secret".to_string()],
+        };
+        let config = Config::layered(&args).await.unwrap();
+        let (_shutdown, draining) = watch::channel(false);
+        let metrics = crate::metrics::Metrics::new(rsys_abi::FILE_DESCRIPTOR_SET).unwrap();
+        let svc = RServic::load_from_config(&config, metrics, draining)
+            .await
+            .unwrap();
+        let resource = svc
+            .manager
+            .create_resource(rsys::generate_random_resource())
+            .await
+            .unwrap();
+        let mut reservation = rsys::generate_random_reservation();
+        reservation.resource_id = resource.id;
+        let mut req = tonic::Request::new(ReserveRequest {
+            reservation: Some(reservation),
+            ..Default::default()
         });
+        req.extensions_mut().insert(rsys::Caller::admin("test"));
         println!("req:{:?}", req);
         let resp = svc.reserve(req).await.unwrap();
         println!("resp:{:?}", resp);
@@ -0,0 +1,132 @@
+use crate::{
+    config::{LogConfig, LogFormat},
+    error::ServError,
+};
+use futures::future::BoxFuture;
+use hyper::header::{HeaderName, HeaderValue};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use rsys::error::RsysError;
+use std::task::{Context, Poll};
+use tonic::Request;
+use tower::{Layer, Service};
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use uuid::Uuid;
+
+/// metadata carrying the id a call is logged under, a client may pick it,
+/// otherwise the server makes one up. responses echo it back.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// longest request id taken from a client
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// flushes exported spans when dropped, keep it until the server is done
+#[must_use]
+pub struct Telemetry {
+    otlp: bool,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if self.otlp {
+            opentelemetry::global::shutdown_tracer_provider();
+        }
+    }
+}
+
+/// install the process wide subscriber `config` describes. exporting over
+/// OTLP needs a tokio runtime to run in.
+pub fn init(config: &LogConfig) -> Result<Telemetry, ServError> {
+    let invalid = |why: String| ServError(RsysError::ConfigError(format!("log: {}", why)));
+    let filter = EnvFilter::try_new(&config.filter).map_err(|err| invalid(err.to_string()))?;
+    let otlp = match &config.otlp {
+        Some(otlp) => {
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(&otlp.endpoint),
+                )
+                .with_trace_config(trace::config().with_resource(Resource::new(vec![
+                    KeyValue::new("service.name", otlp.service_name.clone()),
+                ])))
+                .install_batch(runtime::Tokio)
+                .map_err(|err| invalid(err.to_string()))?;
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        None => None,
+    };
+    let exporting = otlp.is_some();
+    let json = config.format == LogFormat::Json;
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(json.then(|| fmt::layer().json().with_current_span(true)))
+        .with((!json).then(fmt::layer))
+        .with(otlp)
+        .try_init()
+        .map_err(|err| invalid(err.to_string()))?;
+    Ok(Telemetry { otlp: exporting })
+}
+
+/// the id `RequestIdLayer` gave `request`
+pub(crate) fn request_id<T>(request: &Request<T>) -> &str {
+    request
+        .metadata()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+}
+
+/// makes sure every call carries an `x-request-id` and echoes it in the
+/// response
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct RequestIdLayer;
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestIdService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestIdService { inner }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RequestIdService<S> {
+    inner: S,
+}
+
+fn usable(id: &HeaderValue) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.to_str().is_ok()
+}
+
+impl<S, B, R> Service<hyper::Request<B>> for RequestIdService<S>
+where
+    S: Service<hyper::Request<B>, Response = hyper::Response<R>> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: hyper::Request<B>) -> Self::Future {
+        let name = HeaderName::from_static(REQUEST_ID_HEADER);
+        let id = match request.headers().get(&name) {
+            Some(id) if usable(id) => id.clone(),
+            // a uuid is always a valid header value
+            _ => HeaderValue::from_str(&Uuid::new_v4().to_string()).unwrap(),
+        };
+        request.headers_mut().insert(name.clone(), id.clone());
+        let call = self.inner.call(request);
+        Box::pin(async move {
+            let mut response = call.await?;
+            response.headers_mut().insert(name, id);
+            Ok(response)
+        })
+    }
+}
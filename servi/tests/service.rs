@@ -1,11 +1,39 @@
 use futures::StreamExt;
+use prost::Message;
 use rsys_abi::{
-    reservation_service_client::ReservationServiceClient, QueryRequest, ReserveRequest,
+    google::rpc,
+    grpc::{
+        health::v1::{
+            health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest,
+        },
+        reflection::v1alpha::{
+            server_reflection_client::ServerReflectionClient,
+            server_reflection_request::MessageRequest, server_reflection_response::MessageResponse,
+            ServerReflectionRequest,
+        },
+    },
+    reservation_service_client::ReservationServiceClient,
+    resource_service_client::ResourceServiceClient,
+    CancelRequest, CreateResourceRequest, ListenRequest, OperateType, QueryRequest, Reservation,
+    ReservationConflictInfo, ReserveRequest,
+};
+use rsys_servi::{
+    auth::Claims,
+    config::{Args, Config, TlsConfig},
+    spawn_server, ServerHandle,
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time,
+};
+use tonic::{
+    metadata::{Ascii, MetadataValue},
+    service::{interceptor::InterceptedService, Interceptor},
+    transport::{Certificate, Channel, ClientTlsConfig, Identity},
+    Code,
 };
-use rsys_servi::{config::Config, server_start};
-use std::time::Duration;
-use tokio::time;
-use tonic::transport::Channel;
 
 macro_rules! test_server_start {
     ($port:expr) => {
@@ -16,45 +44,122 @@ macro_rules! test_server_start {
     };
 }
 
-async fn test_server_start(port: Option<u16>) -> ReservationServiceClient<Channel> {
-    let mut config = Config::load("../config.yml").await.unwrap();
+/// sends the same bearer token with every request
+#[derive(Clone)]
+struct Bearer(MetadataValue<Ascii>);
+
+impl Interceptor for Bearer {
+    fn call(&mut self, mut req: tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status> {
+        req.metadata_mut().insert("authorization", self.0.clone());
+        Ok(req)
+    }
+}
+
+type Client = ReservationServiceClient<InterceptedService<Channel, Bearer>>;
+
+/// HS256 secret the test servers check tokens with
+const SECRET: &str = "test-secret";
+
+/// `config.yml` with `SECRET`, the file itself ships without one
+async fn test_config() -> Config {
+    let args = Args {
+        config: Some("../config.yml".into()),
+        overrides: vec![format!("auth.hs256_secret={}", SECRET)],
+    };
+    Config::layered(&args).await.unwrap()
+}
+
+/// a token for `sub` signed with `SECRET`
+async fn bearer(sub: &str, roles: &[&str]) -> Bearer {
+    let config = test_config().await;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let claims = Claims {
+        sub: sub.to_string(),
+        iss: config.auth.issuer,
+        aud: config.auth.audience,
+        exp: now + 600,
+        nbf: now,
+        roles: roles.iter().map(|r| r.to_string()).collect(),
+    };
+    let token = jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &claims,
+        &jsonwebtoken::EncodingKey::from_secret(SECRET.as_bytes()),
+    )
+    .unwrap();
+    Bearer(format!("Bearer {}", token).parse().unwrap())
+}
+
+async fn connect(port: u16, bearer: Bearer) -> Client {
+    let url = format!("http://localhost:{}", port);
+    let channel = Channel::from_shared(url).unwrap().connect().await.unwrap();
+    ReservationServiceClient::with_interceptor(channel, bearer)
+}
+
+/// start a server and connect to it as an admin
+async fn test_server_start(port: Option<u16>) -> (ServerHandle, Client) {
+    let mut config = test_config().await;
+    #[allow(clippy::unnecessary_unwrap)]
     if port.is_some() {
         config.server.port = port.unwrap();
     }
+    // tests run side by side, each with its own metrics port
+    config.metrics.port = 0;
     println!("{:?}", config);
-    let url = format!("http://localhost:{}", config.server.port);
+    let port = config.server.port;
 
-    tokio::spawn(async move {
-        let _ = server_start(&config).await;
-        println!("server stop");
-    });
+    let server = spawn_server(&config).await.unwrap();
+    println!("Client connect to {:?}", port);
+    let client = connect(port, bearer("tester", &["admin"]).await).await;
+    (server, client)
+}
 
-    time::sleep(Duration::from_millis(1000)).await;
-    println!("Client connect to {:?}", url);
-    ReservationServiceClient::connect(url).await.unwrap()
+/// a random reservation on a resource registered through the server on `port`
+async fn random_reservation(port: u16) -> Reservation {
+    let url = format!("http://localhost:{}", port);
+    let channel = Channel::from_shared(url).unwrap().connect().await.unwrap();
+    let mut resources =
+        ResourceServiceClient::with_interceptor(channel, bearer("tester", &["admin"]).await);
+    let resource = resources
+        .create(tonic::Request::new(CreateResourceRequest {
+            resource: Some(rsys::generate_random_resource()),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    let mut data = rsys::generate_random_reservation();
+    data.resource_id = resource.id;
+    data
 }
 
 #[tokio::test]
 async fn server_should_work() {
-    let mut client = test_server_start!(50000).await;
+    let (server, mut client) = test_server_start!(50000).await;
 
-    let data = rsys::generate_random_reservation();
+    let data = random_reservation(50000).await;
     println!("{:?}", data);
     let req = tonic::Request::new(ReserveRequest {
         reservation: Some(data),
+        ..Default::default()
     });
     let resp = client.reserve(req).await;
     let data = resp.unwrap().into_inner();
     println!("{:?}", data);
+
+    server.shutdown().await.unwrap();
 }
 
 #[tokio::test]
 async fn load_query_steam() {
-    let mut client = test_server_start!(50001).await;
+    let (server, mut client) = test_server_start!(50001).await;
 
     let uid = "rm_query_manyx";
     let req = tonic::Request::new(QueryRequest {
         uid: uid.to_string(),
+        ..Default::default()
     });
     let resp = client.query(req).await;
     let mut datas = resp.unwrap().into_inner();
@@ -62,4 +167,372 @@ async fn load_query_steam() {
     while let Some(Ok(item)) = datas.next().await {
         println!("{:?}", item);
     }
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn listen_stream_should_work() {
+    let (server, mut client) = test_server_start!(50002).await;
+
+    let resp = client.listen(tonic::Request::new(ListenRequest {})).await;
+    let mut changes = resp.unwrap().into_inner();
+
+    let req = tonic::Request::new(ReserveRequest {
+        reservation: Some(random_reservation(50002).await),
+        ..Default::default()
+    });
+    let data = client.reserve(req).await.unwrap().into_inner();
+
+    while let Some(Ok(item)) = changes.next().await {
+        println!("{:?}", item);
+        if item.reservation.unwrap().id == data.id {
+            assert_eq!(item.operate, OperateType::Create as i32);
+            break;
+        }
+    }
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn conflict_should_carry_details() {
+    let (server, mut client) = test_server_start!(50003).await;
+
+    let data = random_reservation(50003).await;
+    let first = client
+        .reserve(tonic::Request::new(ReserveRequest {
+            reservation: Some(data.clone()),
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let status = client
+        .reserve(tonic::Request::new(ReserveRequest {
+            reservation: Some(data),
+            ..Default::default()
+        }))
+        .await
+        .unwrap_err();
+    println!("{:?}", status);
+
+    let details = rpc::Status::decode(status.details()).unwrap();
+    assert_eq!(
+        details.details[0].type_url,
+        ReservationConflictInfo::TYPE_URL
+    );
+    let info = ReservationConflictInfo::decode(details.details[0].value.as_slice()).unwrap();
+    assert_eq!(info.conflicts.len(), 1);
+    assert_eq!(info.conflicts[0].id, first.id);
+    assert_eq!(info.conflicts[0].uid, first.uid);
+    assert_eq!(status.code(), Code::AlreadyExists);
+    assert_eq!(details.details[1].type_url, rpc::ErrorInfo::TYPE_URL);
+    let reason = rpc::ErrorInfo::decode(details.details[1].value.as_slice()).unwrap();
+    assert_eq!(reason.reason, "ALREADY_BOOKED");
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn reserve_retry_should_not_book_twice() {
+    let (server, mut client) = test_server_start!(50004).await;
+
+    let data = random_reservation(50004).await;
+    let reserve = || {
+        let mut req = tonic::Request::new(ReserveRequest {
+            reservation: Some(data.clone()),
+            ..Default::default()
+        });
+        req.metadata_mut()
+            .insert("idempotency-key", "retry-1".parse().unwrap());
+        req
+    };
+    let first = client.reserve(reserve()).await.unwrap().into_inner();
+    let retry = client.reserve(reserve()).await.unwrap().into_inner();
+    assert_eq!(first.id, retry.id);
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn callers_should_be_authenticated() {
+    let (server, mut admin) = test_server_start!(50005).await;
+
+    let url = "http://localhost:50005";
+    let mut anonymous = ReservationServiceClient::connect(url).await.unwrap();
+    let status = anonymous
+        .get(tonic::Request::new(Default::default()))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    let data = admin
+        .reserve(tonic::Request::new(ReserveRequest {
+            reservation: Some(random_reservation(50005).await),
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    let mut stranger = connect(50005, bearer("stranger", &[]).await).await;
+    let status = stranger
+        .cancel(tonic::Request::new(CancelRequest::from(data.id.clone())))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let mut owner = connect(50005, bearer(&data.uid, &[]).await).await;
+    owner
+        .cancel(tonic::Request::new(CancelRequest::from(data.id)))
+        .await
+        .unwrap();
+
+    server.shutdown().await.unwrap();
+}
+
+/// a certificate for `name` signed by `ca`, as (cert, key) PEM
+fn signed_by(ca: &rcgen::Certificate, name: &str, san: Vec<String>) -> (String, String) {
+    let mut params = rcgen::CertificateParams::new(san);
+    params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, name);
+    let cert = rcgen::Certificate::from_params(params).unwrap();
+    (
+        cert.serialize_pem_with_signer(ca).unwrap(),
+        cert.serialize_private_key_pem(),
+    )
+}
+
+#[tokio::test]
+async fn tls_clients_should_present_certificates() {
+    let mut params = rcgen::CertificateParams::new(vec![]);
+    params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, "rsys test ca");
+    let ca = rcgen::Certificate::from_params(params).unwrap();
+    let ca_pem = ca.serialize_pem().unwrap();
+    let (server_cert, server_key) = signed_by(&ca, "localhost", vec!["localhost".to_string()]);
+    let (client_cert, client_key) = signed_by(&ca, "alice", vec![]);
+
+    let dir = std::env::temp_dir().join(format!("rsys-tls-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let write = |name: &str, pem: &str| {
+        let path = dir.join(name);
+        std::fs::write(&path, pem).unwrap();
+        path.to_string_lossy().to_string()
+    };
+    let mut config = test_config().await;
+    config.server.port = 50006;
+    config.metrics.enabled = false;
+    config.server.tls = Some(TlsConfig {
+        cert: write("server.pem", &server_cert),
+        key: write("server.key", &server_key),
+        client_ca: Some(write("ca.pem", &ca_pem)),
+        client_auth_optional: false,
+    });
+    let server = spawn_server(&config).await.unwrap();
+
+    let endpoint = Channel::from_static("https://localhost:50006");
+    let trust = ClientTlsConfig::new()
+        .domain_name("localhost")
+        .ca_certificate(Certificate::from_pem(&ca_pem));
+    let channel = endpoint
+        .clone()
+        .tls_config(
+            trust
+                .clone()
+                .identity(Identity::from_pem(client_cert, client_key)),
+        )
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+
+    let mut resources = ResourceServiceClient::with_interceptor(
+        channel.clone(),
+        bearer("tester", &["admin"]).await,
+    );
+    let resource = resources
+        .create(tonic::Request::new(CreateResourceRequest {
+            resource: Some(rsys::generate_random_resource()),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    // no token, the certificate's common name is the caller
+    let mut data = rsys::generate_random_reservation();
+    data.resource_id = resource.id;
+    data.uid = String::new();
+    let mut alice = ReservationServiceClient::new(channel);
+    let reserved = alice
+        .reserve(tonic::Request::new(ReserveRequest {
+            reservation: Some(data),
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(reserved.uid, "alice");
+
+    // the handshake fails without a client certificate
+    let anonymous = match endpoint.tls_config(trust).unwrap().connect().await {
+        Ok(channel) => ReservationServiceClient::new(channel)
+            .get(tonic::Request::new(Default::default()))
+            .await
+            .is_err(),
+        Err(_) => true,
+    };
+    assert!(anonymous);
+    server.shutdown().await.unwrap();
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn health_and_reflection_should_work() {
+    let (server, _) = test_server_start!(50007).await;
+
+    // neither needs a token
+    let url = "http://localhost:50007";
+    let mut health = HealthClient::connect(url).await.unwrap();
+    let status = health
+        .check(tonic::Request::new(HealthCheckRequest {
+            service: "reservation.ReservationService".to_string(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(status.status(), ServingStatus::Serving);
+    let status = health
+        .check(tonic::Request::new(HealthCheckRequest {
+            service: "nope".to_string(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    let mut watch = health
+        .watch(tonic::Request::new(HealthCheckRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let status = watch.next().await.unwrap().unwrap();
+    assert_eq!(status.status(), ServingStatus::Serving);
+
+    let mut reflection = ServerReflectionClient::connect(url).await.unwrap();
+    let request = ServerReflectionRequest {
+        host: String::new(),
+        message_request: Some(MessageRequest::ListServices(String::new())),
+    };
+    let mut responses = reflection
+        .server_reflection_info(futures::stream::iter(vec![request]))
+        .await
+        .unwrap()
+        .into_inner();
+    let response = responses.next().await.unwrap().unwrap();
+    let Some(MessageResponse::ListServicesResponse(list)) = response.message_response else {
+        panic!("{:?}", response);
+    };
+    assert!(list
+        .service
+        .iter()
+        .any(|s| s.name == "reservation.ReservationService"));
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn shutdown_should_end_streams() {
+    let (server, mut client) = test_server_start!(50008).await;
+
+    let mut changes = client
+        .listen(tonic::Request::new(ListenRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+    let shutdown = tokio::spawn(server.shutdown());
+
+    let status = time::timeout(Duration::from_secs(5), changes.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+    time::timeout(Duration::from_secs(5), shutdown)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert!(Channel::from_static("http://localhost:50008")
+        .connect()
+        .await
+        .is_err());
+}
+
+/// the body of `GET /metrics` on `server`
+async fn scrape(server: &ServerHandle) -> String {
+    let mut conn = TcpStream::connect(server.metrics_addr().unwrap())
+        .await
+        .unwrap();
+    conn.write_all(b"GET /metrics HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    conn.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    response
+}
+
+#[tokio::test]
+async fn metrics_should_count_calls() {
+    let (server, mut client) = test_server_start!(50009).await;
+
+    let data = random_reservation(50009).await;
+    for _ in 0..2 {
+        let _ = client
+            .reserve(tonic::Request::new(ReserveRequest {
+                reservation: Some(data.clone()),
+                ..Default::default()
+            }))
+            .await;
+    }
+    // a replayed key hands back the first booking without creating another
+    let keyed = random_reservation(50009).await;
+    for _ in 0..2 {
+        client
+            .reserve(tonic::Request::new(ReserveRequest {
+                reservation: Some(keyed.clone()),
+                idempotency_key: "metrics-1".to_string(),
+                ..Default::default()
+            }))
+            .await
+            .unwrap();
+    }
+    let changes = client
+        .listen(tonic::Request::new(ListenRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let metrics = scrape(&server).await;
+    println!("{}", metrics);
+    for line in [
+        r#"rsys_rpc_requests_total{method="/reservation.ReservationService/reserve"} 4"#,
+        r#"rsys_rpc_errors_total{error="AlreadyBooked",method="/reservation.ReservationService/reserve"} 1"#,
+        r#"rsys_reservations_total{event="created"} 2"#,
+        r#"rsys_active_streams{method="listen"} 1"#,
+        r#"rsys_rpc_duration_seconds_count{method="/reservation.ResourceService/create"} 2"#,
+    ] {
+        assert!(metrics.lines().any(|l| l == line), "missing {}", line);
+    }
+    assert!(metrics.contains(r#"rsys_db_pool_connections{state="max"} 10"#));
+
+    drop(changes);
+    time::sleep(Duration::from_millis(100)).await;
+    let metrics = scrape(&server).await;
+    assert!(metrics.contains(r#"rsys_active_streams{method="listen"} 0"#));
+
+    server.shutdown().await.unwrap();
 }
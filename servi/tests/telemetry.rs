@@ -0,0 +1,153 @@
+use hyper::{
+    body::Bytes,
+    service::{make_service_fn, service_fn},
+    Body, HeaderMap, Server,
+};
+use rsys_abi::{reservation_service_client::ReservationServiceClient, GetRequest};
+use rsys_servi::{
+    auth::Claims,
+    config::{Args, Config, OtlpConfig},
+    spawn_server,
+    telemetry::{self, REQUEST_ID_HEADER},
+};
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc;
+use tonic::transport::Channel;
+
+/// an OTLP/gRPC collector that keeps the raw body of every export call and
+/// answers each with an empty `ExportTraceServiceResponse`
+async fn collector() -> (SocketAddr, mpsc::UnboundedReceiver<Bytes>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let make = make_service_fn(move |_| {
+        let tx = tx.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                let tx = tx.clone();
+                async move {
+                    let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                    let _ = tx.send(body);
+                    let (mut sender, body) = Body::channel();
+                    tokio::spawn(async move {
+                        // an uncompressed frame holding an empty message
+                        sender.send_data(Bytes::from_static(&[0; 5])).await?;
+                        let mut trailers = HeaderMap::new();
+                        trailers.insert("grpc-status", "0".parse().unwrap());
+                        sender.send_trailers(trailers).await
+                    });
+                    Ok::<_, Infallible>(
+                        hyper::Response::builder()
+                            .header("content-type", "application/grpc")
+                            .body(body)
+                            .unwrap(),
+                    )
+                }
+            }))
+        }
+    });
+    let server = Server::bind(&"127.0.0.1:0".parse().unwrap())
+        .http2_only(true)
+        .serve(make);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    (addr, rx)
+}
+
+fn contains(haystack: &[u8], needle: &str) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|w| w == needle.as_bytes())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn spans_should_reach_the_collector() {
+    let (collector, mut exports) = collector().await;
+    let args = Args {
+        config: Some("../config.yml".into()),
+        overrides: vec!["auth.hs256_secret=test-secret".to_string()],
+    };
+    let mut config = Config::layered(&args).await.unwrap();
+    config.server.port = 50010;
+    config.metrics.enabled = false;
+    config.log.otlp = Some(OtlpConfig {
+        endpoint: format!("http://{}", collector),
+        service_name: "rsys-telemetry-test".to_string(),
+    });
+    let telemetry = telemetry::init(&config.log).unwrap();
+    let server = spawn_server(&config).await.unwrap();
+
+    let channel = Channel::from_static("http://localhost:50010")
+        .connect()
+        .await
+        .unwrap();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let claims = Claims {
+        sub: "tracer".to_string(),
+        iss: config.auth.issuer.clone(),
+        aud: config.auth.audience.clone(),
+        exp: now + 600,
+        nbf: now,
+        roles: vec![],
+    };
+    let token = jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &claims,
+        &jsonwebtoken::EncodingKey::from_secret(b"test-secret"),
+    )
+    .unwrap();
+    let mut client = ReservationServiceClient::new(channel);
+
+    let mut request = tonic::Request::new(GetRequest {
+        id: "00000000-0000-0000-0000-000000000000".to_string(),
+    });
+    let metadata = request.metadata_mut();
+    metadata.insert(
+        "authorization",
+        format!("Bearer {}", token).parse().unwrap(),
+    );
+    metadata.insert(REQUEST_ID_HEADER, "telemetry-test-42".parse().unwrap());
+    let status = client.get(request).await.unwrap_err();
+    assert_eq!(
+        status.metadata().get(REQUEST_ID_HEADER).unwrap(),
+        "telemetry-test-42"
+    );
+
+    // without one the server makes one up
+    let mut request = tonic::Request::new(GetRequest::default());
+    request.metadata_mut().insert(
+        "authorization",
+        format!("Bearer {}", token).parse().unwrap(),
+    );
+    let status = client.get(request).await.unwrap_err();
+    let generated = status.metadata().get(REQUEST_ID_HEADER).unwrap();
+    assert_eq!(generated.len(), 36);
+
+    server.shutdown().await.unwrap();
+    // flushes the spans still queued
+    tokio::task::spawn_blocking(move || drop(telemetry))
+        .await
+        .unwrap();
+
+    let mut exported = vec![];
+    while let Ok(body) = exports.try_recv() {
+        exported.extend_from_slice(&body);
+    }
+    for expected in [
+        "rsys-telemetry-test",
+        "telemetry-test-42",
+        // the uid field of the span rsys opens
+        "tracer",
+    ] {
+        assert!(
+            contains(&exported, expected),
+            "no {} in the export",
+            expected
+        );
+    }
+}